@@ -0,0 +1,231 @@
+// Generates the C header for the API in src/ffi.rs, so the header can't drift from the code.
+// The header is written to OUT_DIR on every build, and a test in src/ffi.rs checks that the
+// committed include/minigrep.h matches it. `MINIGREP_UPDATE_HEADER=1 cargo build` writes it
+// to include/minigrep.h as well.
+//
+// src/ffi.rs is read line by line rather than parsed, so it has to keep to a few shapes, all
+// starting at the first column:
+//
+//   // Section heading.            a comment right before a group of constants
+//   pub const NAME: i32 = 3;       a #define; the value is a number or `1 << n`
+//   pub struct Name { ... }        an opaque type, or a struct with `#[repr(C)]`
+//   pub unsafe extern "C" fn ...   a function, with its `{` at the end of the signature
+//
+// `///` docs become C comments (a function's only up to its `# Safety` section), and Rust
+// type names become snake_case C names.
+
+#![allow(clippy::needless_return)]
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+const SOURCE: &str = "src/ffi.rs";
+const HEADER: &str = "include/minigrep.h";
+
+const PREAMBLE: &str = "\
+/*
+ * minigrep.h - C API for the minigrep search engine.
+ *
+ * Link against the static (libch12_cli.a) or shared (libch12_cli.so) library built by
+ * `cargo build --release`.
+ *
+ * Every function that can fail returns one of the MINIGREP_* status codes. On failure,
+ * if `error` is not NULL it receives a message that must be freed with
+ * minigrep_string_free(). No function ever unwinds into the caller.
+ *
+ * Generated from src/ffi.rs by build.rs; don't edit it by hand. Regenerate it with
+ * `MINIGREP_UPDATE_HEADER=1 cargo build`.
+ */
+#ifndef MINIGREP_H
+#define MINIGREP_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern \"C\" {
+#endif
+";
+
+const POSTAMBLE: &str = "
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* MINIGREP_H */
+";
+
+fn main() {
+    println!("cargo::rerun-if-changed={SOURCE}");
+    println!("cargo::rerun-if-env-changed=MINIGREP_UPDATE_HEADER");
+    let source: String = fs::read_to_string(SOURCE).expect("can't read src/ffi.rs");
+    let header: String = header(&source);
+    let out_dir: PathBuf = PathBuf::from(env::var_os("OUT_DIR").expect("OUT_DIR isn't set"));
+    fs::write(out_dir.join("minigrep.h"), &header).expect("can't write the header to OUT_DIR");
+    if env::var_os("MINIGREP_UPDATE_HEADER").is_some() {
+        fs::write(HEADER, &header).expect("can't write include/minigrep.h");
+    }
+}
+
+fn header(source: &str) -> String {
+    let mut out: String = String::from(PREAMBLE);
+    let mut lines = source.lines();
+    let mut docs: Vec<String> = Vec::new();
+    let mut heading: Option<String> = None;
+    let mut repr_c: bool = false;
+    let mut in_constants: bool = false;
+    while let Some(line) = lines.next() {
+        if let Some(doc) = line.strip_prefix("///") {
+            docs.push(doc.strip_prefix(' ').unwrap_or(doc).to_string());
+            continue;
+        }
+        if let Some(comment) = line.strip_prefix("// ") {
+            heading = Some(comment.to_string());
+            continue;
+        }
+        if line == "#[repr(C)]" {
+            repr_c = true;
+            continue;
+        }
+        if line.starts_with("#[") {
+            continue;
+        }
+        if let Some(constant) = line.strip_prefix("pub const ") {
+            if !in_constants || heading.is_some() {
+                out.push('\n');
+            }
+            if let Some(heading) = heading.take() {
+                out.push_str(&format!("/* {heading} */\n"));
+            }
+            let (name, rest) = constant.split_once(':').expect("a constant without a type");
+            let value: &str = rest.split_once('=').expect("a constant without a value").1.trim().trim_end_matches(';');
+            out.push_str(&format!("#define {name} {}", evaluate(value)));
+            if !docs.is_empty() {
+                out.push_str(&format!(" /* {} */", docs.join(" ")));
+            }
+            out.push('\n');
+            in_constants = true;
+        } else if let Some(declaration) = line.strip_prefix("pub struct ") {
+            let name: &str = declaration.trim_end_matches(['{', ' ']);
+            out.push('\n');
+            push_comment(&mut out, &docs);
+            if repr_c {
+                out.push_str("typedef struct {\n");
+                for field in lines.by_ref().take_while(|line| *line != "}") {
+                    let (field, ty) = field.trim().trim_start_matches("pub ").split_once(':').expect("a field without a type");
+                    out.push_str(&format!("    {};\n", declare(ty.trim().trim_end_matches(','), field)));
+                }
+                out.push_str(&format!("}} {};\n", snake_case(name)));
+            } else {
+                if declaration.ends_with('{') {
+                    lines.by_ref().take_while(|line| *line != "}").for_each(drop);
+                }
+                out.push_str(&format!("typedef struct {name} {};\n", snake_case(name)));
+            }
+            in_constants = false;
+        } else if let Some(signature) = line.strip_prefix("pub unsafe extern \"C\" fn ") {
+            let mut signature: String = signature.to_string();
+            while !signature.ends_with('{') {
+                signature.push_str(lines.next().expect("a function signature without a body").trim());
+            }
+            out.push('\n');
+            let summary: Vec<String> = docs.iter().take_while(|doc| !doc.starts_with("# Safety")).cloned().collect();
+            push_comment(&mut out, &summary);
+            out.push_str(&function(signature.trim_end_matches('{').trim()));
+            in_constants = false;
+        }
+        docs.clear();
+        heading = None;
+        repr_c = false;
+    }
+    out.push_str(POSTAMBLE);
+    return out;
+}
+
+// A constant's value: a number, or `1 << n`.
+fn evaluate(value: &str) -> u64 {
+    if let Some((base, shift)) = value.split_once("<<") {
+        return base.trim().parse::<u64>().expect("a constant that isn't a number") << shift.trim().parse::<u32>().expect("a bad shift");
+    }
+    return value.parse().expect("a constant that isn't a number");
+}
+
+// `docs` as a C comment on the line(s) before a declaration, without trailing blank lines.
+fn push_comment(out: &mut String, docs: &[String]) {
+    let mut docs: &[String] = docs;
+    while let Some((last, rest)) = docs.split_last()
+        && last.is_empty()
+    {
+        docs = rest;
+    }
+    match docs {
+        [] => {}
+        [line] => out.push_str(&format!("/* {line} */\n")),
+        lines => {
+            out.push_str("/*\n");
+            for line in lines {
+                out.push_str(&if line.is_empty() { String::from(" *\n") } else { format!(" * {line}\n") });
+            }
+            out.push_str(" */\n");
+        }
+    }
+}
+
+// `name(param: type, ...) -> ret` as a C prototype.
+fn function(signature: &str) -> String {
+    let (name, rest) = signature.split_once('(').expect("a function without parameters");
+    let (params, ret) = rest.rsplit_once(')').expect("a function without a closing parenthesis");
+    let ret: String = match ret.trim().strip_prefix("->") {
+        Some(ty) => c_type(ty.trim()),
+        None => String::from("void"),
+    };
+    let params: Vec<String> = params
+        .split(',')
+        .map(|param| param.trim())
+        .filter(|param| !param.is_empty())
+        .map(|param| {
+            let (name, ty) = param.split_once(':').expect("a parameter without a type");
+            return declare(ty.trim(), name.trim());
+        })
+        .collect();
+    return format!("{} {name}({});\n", ret, params.join(", "));
+}
+
+// A C declaration of `name` with the Rust type `ty`.
+fn declare(ty: &str, name: &str) -> String {
+    let ty: String = c_type(ty);
+    return if ty.ends_with('*') { format!("{ty}{name}") } else { format!("{ty} {name}") };
+}
+
+fn c_type(ty: &str) -> String {
+    if let Some(pointee) = ty.strip_prefix("*const ") {
+        return pointer(format!("const {}", c_type(pointee)));
+    }
+    if let Some(pointee) = ty.strip_prefix("*mut ") {
+        return pointer(c_type(pointee));
+    }
+    return match ty {
+        "c_char" => String::from("char"),
+        "i32" => String::from("int32_t"),
+        "u32" => String::from("uint32_t"),
+        "usize" => String::from("size_t"),
+        name => snake_case(name),
+    };
+}
+
+fn pointer(pointee: String) -> String {
+    return if pointee.ends_with('*') { format!("{pointee}*") } else { format!("{pointee} *") };
+}
+
+// MinigrepQuery -> minigrep_query.
+fn snake_case(name: &str) -> String {
+    let mut snake: String = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_ascii_uppercase() && i > 0 {
+            snake.push('_');
+        }
+        snake.push(c.to_ascii_lowercase());
+    }
+    return snake;
+}
@@ -0,0 +1,90 @@
+/*
+ * minigrep.h - C API for the minigrep search engine.
+ *
+ * Link against the static (libch12_cli.a) or shared (libch12_cli.so) library built by
+ * `cargo build --release`.
+ *
+ * Every function that can fail returns one of the MINIGREP_* status codes. On failure,
+ * if `error` is not NULL it receives a message that must be freed with
+ * minigrep_string_free(). No function ever unwinds into the caller.
+ *
+ * Generated from src/ffi.rs by build.rs; don't edit it by hand. Regenerate it with
+ * `MINIGREP_UPDATE_HEADER=1 cargo build`.
+ */
+#ifndef MINIGREP_H
+#define MINIGREP_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Status codes. */
+#define MINIGREP_OK 0
+#define MINIGREP_ERR_NULL 1 /* a required pointer was NULL */
+#define MINIGREP_ERR_UTF8 2 /* a string, buffer or file was not valid UTF-8 */
+#define MINIGREP_ERR_QUERY 3 /* the pattern could not be compiled */
+#define MINIGREP_ERR_IO 4 /* a file could not be read */
+#define MINIGREP_ERR_RANGE 5 /* a record index was out of range */
+#define MINIGREP_ERR_PANIC 6 /* an internal error was caught */
+
+/* Query flags, combined with |. */
+#define MINIGREP_IGNORE_CASE 1
+#define MINIGREP_SMART_CASE 2 /* ignore case unless the pattern has uppercase letters */
+#define MINIGREP_REGEX 4
+#define MINIGREP_BOOLEAN 8 /* AND / OR / NOT queries */
+#define MINIGREP_MULTILINE 16 /* matches may span lines */
+#define MINIGREP_NORMALIZE 32 /* NFC and NFD text match each other */
+#define MINIGREP_FOLD_ACCENTS 64 /* also ignore accents */
+
+/* A compiled query. Opaque to C. */
+typedef struct MinigrepQuery minigrep_query;
+
+/* The match records of one search. Opaque to C. */
+typedef struct MinigrepResults minigrep_results;
+
+/*
+ * One match. Offsets are in bytes from the start of the searched buffer or file; the line
+ * runs from line_start to line_end, without its newline. A line that matched without a
+ * particular match (a boolean `NOT` query) gets one record with an empty match at the start
+ * of the line.
+ */
+typedef struct {
+    size_t line_number;
+    size_t line_start;
+    size_t line_end;
+    size_t match_start;
+    size_t match_end;
+} minigrep_match;
+
+/* Compiles `pattern` with the MINIGREP_* `flags`. */
+int32_t minigrep_query_new(const char *pattern, uint32_t flags, minigrep_query **out, char **error);
+
+/* Frees a query. Null is ignored. */
+void minigrep_query_free(minigrep_query *query);
+
+/* Searches `len` bytes of UTF-8 text at `buffer`. */
+int32_t minigrep_search_buffer(const minigrep_query *query, const char *buffer, size_t len, minigrep_results **out, char **error);
+
+/* Reads the file at `path` and searches it. Offsets are from the start of the file. */
+int32_t minigrep_search_file(const minigrep_query *query, const char *path, minigrep_results **out, char **error);
+
+/* The number of match records, or 0 for null. */
+size_t minigrep_results_len(const minigrep_results *results);
+
+/* Copies record `index` into `out`. */
+int32_t minigrep_results_get(const minigrep_results *results, size_t index, minigrep_match *out);
+
+/* Frees search results. Null is ignored. */
+void minigrep_results_free(minigrep_results *results);
+
+/* Frees an error message. Null is ignored. */
+void minigrep_string_free(char *text);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* MINIGREP_H */
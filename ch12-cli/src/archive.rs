@@ -0,0 +1,164 @@
+// Searching inside compressed files and archives. Gzip streams are decompressed on the fly and
+// tar archives (compressed or not) are walked member by member, so an archive is never held in
+// memory as a whole. What kind of file something is comes from its name:
+//
+//   .gz            one gzip-compressed file, reported under the archive's own name
+//   .tar           a tar archive, each regular member reported as `archive.tar:member/path`
+//   .tar.gz, .tgz  a gzip-compressed tar archive, reported the same way
+//
+// Member contents are handed out through LineChunks, which reads whole lines a chunk at a time
+// so line-by-line searches only ever hold one chunk of a member.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+
+use flate2::read::MultiGzDecoder;
+
+/// How many bytes LineChunks reads before looking for the end of the line.
+pub const CHUNK_SIZE: usize = 256 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Gzip,
+    Tar,
+    TarGzip,
+}
+
+/// What kind of archive `path` is, if any.
+pub fn kind(path: &Path) -> Option<Kind> {
+    let name: String = path.file_name()?.to_string_lossy().to_lowercase();
+    if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        return Some(Kind::TarGzip);
+    }
+    if name.ends_with(".tar") {
+        return Some(Kind::Tar);
+    }
+    if name.ends_with(".gz") {
+        return Some(Kind::Gzip);
+    }
+    return None;
+}
+
+/// Calls `on_member` with the name and a reader for each file in the archive at `path`. A gzip
+/// file has one unnamed member; tar members that aren't regular files are skipped.
+pub fn for_each_member<F>(path: &Path, kind: Kind, mut on_member: F) -> io::Result<()>
+where
+    F: FnMut(Option<&str>, &mut dyn Read) -> io::Result<()>,
+{
+    let file: BufReader<File> = BufReader::new(File::open(path)?);
+    return match kind {
+        Kind::Gzip => on_member(None, &mut MultiGzDecoder::new(file)),
+        Kind::Tar => for_each_tar_member(file, on_member),
+        Kind::TarGzip => for_each_tar_member(MultiGzDecoder::new(file), on_member),
+    };
+}
+
+fn for_each_tar_member<R, F>(reader: R, mut on_member: F) -> io::Result<()>
+where
+    R: Read,
+    F: FnMut(Option<&str>, &mut dyn Read) -> io::Result<()>,
+{
+    let mut archive: tar::Archive<R> = tar::Archive::new(reader);
+    for entry in archive.entries()? {
+        let mut entry: tar::Entry<R> = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let name: String = entry.path()?.to_string_lossy().into_owned();
+        on_member(Some(&name), &mut entry)?;
+    }
+    return Ok(());
+}
+
+/// Reads a stream as chunks of whole lines.
+pub struct LineChunks<R: Read> {
+    reader: BufReader<R>,
+    chunk_size: usize,
+}
+
+impl<R: Read> LineChunks<R> {
+    pub fn new(reader: R, chunk_size: usize) -> LineChunks<R> {
+        return LineChunks { reader: BufReader::new(reader), chunk_size };
+    }
+
+    /// Replaces `chunk` with the next `chunk_size` bytes or so, extended to the end of the
+    /// line. Returns false at the end of the stream. Fails with InvalidData if the chunk isn't
+    /// UTF-8; since chunks end at newlines, a character is never split between two.
+    pub fn next_chunk(&mut self, chunk: &mut String) -> io::Result<bool> {
+        let mut bytes: Vec<u8> = Vec::new();
+        Read::by_ref(&mut self.reader).take(self.chunk_size as u64).read_to_end(&mut bytes)?;
+        if bytes.is_empty() {
+            chunk.clear();
+            return Ok(false);
+        }
+        if !bytes.ends_with(b"\n") {
+            self.reader.read_until(b'\n', &mut bytes)?;
+        }
+        *chunk = String::from_utf8(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        return Ok(true);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+    use std::fs;
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    use crate::test_util::scratch_dir;
+
+    fn members(path: &Path) -> Vec<(Option<String>, String)> {
+        let mut found: Vec<(Option<String>, String)> = Vec::new();
+        for_each_member(path, kind(path).unwrap(), |name, reader| {
+            let mut text: String = String::new();
+            reader.read_to_string(&mut text)?;
+            found.push((name.map(String::from), text));
+            return Ok(());
+        })
+        .unwrap();
+        return found;
+    }
+
+    #[test]
+    fn kinds_from_names() {
+        assert_eq!(Some(Kind::TarGzip), kind(Path::new("release.TGZ")));
+        assert_eq!(Some(Kind::Gzip), kind(Path::new("app.log.1.gz")));
+        assert_eq!(None, kind(Path::new("notes.txt")));
+    }
+
+    #[test]
+    fn reads_gzip_and_tar_members() {
+        let dir: PathBuf = scratch_dir("archive", &[]);
+
+        let mut gzip: GzEncoder<Vec<u8>> = GzEncoder::new(Vec::new(), Compression::default());
+        gzip.write_all(b"one\ntwo\n").unwrap();
+        let gz_path: PathBuf = dir.join("app.log.gz");
+        fs::write(&gz_path, gzip.finish().unwrap()).unwrap();
+        assert_eq!(vec![(None, String::from("one\ntwo\n"))], members(&gz_path));
+
+        let tgz_path: PathBuf = dir.join("release.tar.gz");
+        let mut builder = tar::Builder::new(GzEncoder::new(File::create(&tgz_path).unwrap(), Compression::default()));
+        let mut header: tar::Header = tar::Header::new_gnu();
+        header.set_size(6);
+        header.set_mode(0o644);
+        builder.append_data(&mut header, "docs/README", &b"hello\n"[..]).unwrap();
+        builder.into_inner().unwrap().finish().unwrap();
+        assert_eq!(vec![(Some(String::from("docs/README")), String::from("hello\n"))], members(&tgz_path));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn chunks_end_on_line_boundaries() {
+        let mut chunks: LineChunks<&[u8]> = LineChunks::new(&b"alpha\nbeta\ngamma"[..], 3);
+        let mut chunk: String = String::new();
+        let mut found: Vec<String> = Vec::new();
+        while chunks.next_chunk(&mut chunk).unwrap() {
+            found.push(chunk.clone());
+        }
+        assert_eq!(vec!["alpha\n", "beta\n", "gamma"], found);
+    }
+}
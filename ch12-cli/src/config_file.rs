@@ -0,0 +1,217 @@
+// Defaults for command-line options, read from a config file so they don't have to be typed
+// every time. Keys are long option names without the leading dashes:
+//
+//   # applies to every search
+//   line-number = true
+//
+//   [profile.logs]
+//   ignore-case = true
+//   regex = true
+//
+// `key = true` turns an option on, `key = false` turns it off and any other value is passed as
+// the option's argument. Keys before the first section are defaults; a `[profile.NAME]`
+// section is only used when `--profile NAME` is given. The file's settings are turned back
+// into arguments and placed before the real ones, so the command line always wins. Only
+// options can be set: a key that picks what minigrep does, like `files` or `follow`, is an
+// error, so the config file can't quietly turn a search into something else.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::error::Error;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Environment variable naming the config file to use instead of the one in the user's
+/// config directory.
+pub const CONFIG_ENV_VAR: &str = "MINIGREP_CONFIG";
+
+/// Keys that choose the command or mode rather than set an option, which the config file
+/// isn't allowed to change.
+const NOT_OPTIONS: &[&str] = &["print-config", "files", "interactive", "follow", "watch", "rank", "profile", "config", "no-config"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigFileError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigFileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "line {}: {}", self.line, self.message);
+    }
+}
+
+impl Error for ConfigFileError {}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConfigFile {
+    defaults: Vec<(String, String)>,
+    profiles: BTreeMap<String, Vec<(String, String)>>,
+}
+
+impl ConfigFile {
+    /// Where the config file is looked for: the path in MINIGREP_CONFIG if it's set, otherwise
+    /// `minigrep/config` in the user's config directory.
+    pub fn default_path() -> Option<PathBuf> {
+        return ConfigFile::path_from(|name| env::var_os(name));
+    }
+
+    /// `default_path`, looking environment variables up with `var`.
+    fn path_from(var: impl Fn(&str) -> Option<OsString>) -> Option<PathBuf> {
+        if let Some(path) = var(CONFIG_ENV_VAR) {
+            return Some(PathBuf::from(path));
+        }
+        let config_dir: PathBuf = if let Some(dir) = var("XDG_CONFIG_HOME") {
+            PathBuf::from(dir)
+        } else if let Some(dir) = var("APPDATA") {
+            PathBuf::from(dir)
+        } else {
+            PathBuf::from(var("HOME")?).join(".config")
+        };
+        return Some(config_dir.join("minigrep").join("config"));
+    }
+
+    /// Reads the config file from its default location. A missing file in the config directory
+    /// is fine, but a file named by MINIGREP_CONFIG has to exist.
+    pub fn load_default() -> Result<Option<(PathBuf, ConfigFile)>, Box<dyn Error>> {
+        return ConfigFile::load_from(|name| env::var_os(name));
+    }
+
+    /// `load_default`, looking environment variables up with `var`.
+    pub(crate) fn load_from(var: impl Fn(&str) -> Option<OsString>) -> Result<Option<(PathBuf, ConfigFile)>, Box<dyn Error>> {
+        let path: PathBuf = match ConfigFile::path_from(&var) {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        if !path.exists() && var(CONFIG_ENV_VAR).is_none() {
+            return Ok(None);
+        }
+        let file: ConfigFile = ConfigFile::load(&path)?;
+        return Ok(Some((path, file)));
+    }
+
+    pub fn load(path: &Path) -> Result<ConfigFile, Box<dyn Error>> {
+        let text: String = fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        return ConfigFile::parse(&text).map_err(|e| format!("{}: {e}", path.display()).into());
+    }
+
+    pub fn parse(text: &str) -> Result<ConfigFile, ConfigFileError> {
+        let mut file: ConfigFile = ConfigFile::default();
+        let mut section: Option<String> = None;
+        for (index, raw) in text.lines().enumerate() {
+            let line: &str = raw.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: &str| ConfigFileError { line: index + 1, message: message.to_string() };
+            if let Some(header) = line.strip_prefix('[') {
+                let header: &str = header.strip_suffix(']').ok_or_else(|| error("missing ']'"))?.trim();
+                let name: &str = header.strip_prefix("profile.").ok_or_else(|| error("sections must be [profile.NAME]"))?;
+                if name.is_empty() {
+                    return Err(error("profile name is empty"));
+                }
+                file.profiles.entry(name.to_string()).or_default();
+                section = Some(name.to_string());
+                continue;
+            }
+            let (key, value) = line.split_once('=').ok_or_else(|| error("expected `key = value`"))?;
+            let key: &str = key.trim();
+            if key.is_empty() {
+                return Err(error("missing key"));
+            }
+            if key.starts_with('-') {
+                return Err(error("keys are option names without the leading dashes"));
+            }
+            if NOT_OPTIONS.contains(&key) {
+                return Err(error(&format!("`{key}` isn't an option and can't be set in the config file")));
+            }
+            let value: String = unquote(value.trim());
+            let entries: &mut Vec<(String, String)> = match &section {
+                Some(name) => file.profiles.get_mut(name).unwrap(),
+                None => &mut file.defaults,
+            };
+            entries.push((key.to_string(), value));
+        }
+        return Ok(file);
+    }
+
+    pub fn profile_names(&self) -> Vec<&str> {
+        return self.profiles.keys().map(|name| name.as_str()).collect();
+    }
+
+    /// The defaults, followed by the named profile if there is one, as command-line arguments.
+    pub fn args(&self, profile: Option<&str>) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut args: Vec<String> = Vec::new();
+        push_args(&mut args, &self.defaults);
+        if let Some(name) = profile {
+            match self.profiles.get(name) {
+                Some(entries) => push_args(&mut args, entries),
+                None => return Err(format!("no profile named `{name}` in the config file").into()),
+            }
+        }
+        return Ok(args);
+    }
+}
+
+fn push_args(args: &mut Vec<String>, entries: &[(String, String)]) {
+    for (key, value) in entries {
+        match value.as_str() {
+            "true" => args.push(format!("--{key}")),
+            "false" => args.push(format!("--no-{key}")),
+            _ => {
+                args.push(format!("--{key}"));
+                args.push(value.clone());
+            }
+        }
+    }
+}
+
+fn unquote(value: &str) -> String {
+    if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        return value[1..value.len() - 1].to_string();
+    }
+    return value.to_string();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "\
+# defaults
+line-number = true
+ignore-case = false
+
+[profile.logs]
+ignore-case = true
+glob = \"*.log\"
+";
+
+    #[test]
+    fn defaults_then_profile() {
+        let file: ConfigFile = ConfigFile::parse(TEXT).unwrap();
+        assert_eq!(vec!["--line-number", "--no-ignore-case"], file.args(None).unwrap());
+        assert_eq!(
+            vec!["--line-number", "--no-ignore-case", "--ignore-case", "--glob", "*.log"],
+            file.args(Some("logs")).unwrap()
+        );
+        assert!(file.args(Some("missing")).is_err());
+    }
+
+    #[test]
+    fn errors_have_line_numbers() {
+        let err: ConfigFileError = ConfigFile::parse("regex = true\n[logs]\n").unwrap_err();
+        assert_eq!(2, err.line);
+        assert_eq!(3, ConfigFile::parse("\n\nnot a setting\n").unwrap_err().line);
+    }
+
+    #[test]
+    fn only_options_can_be_set() {
+        assert_eq!(1, ConfigFile::parse("files = true\n").unwrap_err().line);
+        assert_eq!(3, ConfigFile::parse("regex = true\n[profile.tail]\nfollow = true\n").unwrap_err().line);
+        assert!(ConfigFile::parse("interactive = false\n").is_err());
+        assert!(ConfigFile::parse("--regex = true\n").is_err());
+    }
+}
@@ -0,0 +1,387 @@
+// The C API, for programs that want to use the search engine without running minigrep. The
+// declarations in include/minigrep.h are generated from this file by build.rs, and a test
+// below checks that the committed header is up to date. That keeps the top-level items here to
+// the few shapes build.rs understands.
+//
+// The shape of the API:
+//
+//   minigrep_query_new       compile a pattern with MINIGREP_* option flags
+//   minigrep_search_buffer   search a UTF-8 buffer, or
+//   minigrep_search_file     read a file and search it
+//   minigrep_results_len     how many match records a search produced
+//   minigrep_results_get     copy out one record: line number, line and match offsets
+//   *_free                   release queries, results and error strings
+//
+// Every function returns a MINIGREP_* status and catches panics, which must never unwind into
+// C. On failure the optional `error` out-parameter receives a message the caller frees with
+// minigrep_string_free. Null handles are accepted by the free functions and rejected, with
+// MINIGREP_ERR_NULL, everywhere else.
+
+use std::ffi::{CStr, CString, c_char};
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::normalize::Normalization;
+use crate::searcher::{Searcher, SearcherBuilder};
+
+// Status codes.
+pub const MINIGREP_OK: i32 = 0;
+/// a required pointer was NULL
+pub const MINIGREP_ERR_NULL: i32 = 1;
+/// a string, buffer or file was not valid UTF-8
+pub const MINIGREP_ERR_UTF8: i32 = 2;
+/// the pattern could not be compiled
+pub const MINIGREP_ERR_QUERY: i32 = 3;
+/// a file could not be read
+pub const MINIGREP_ERR_IO: i32 = 4;
+/// a record index was out of range
+pub const MINIGREP_ERR_RANGE: i32 = 5;
+/// an internal error was caught
+pub const MINIGREP_ERR_PANIC: i32 = 6;
+
+// Query flags, combined with |.
+pub const MINIGREP_IGNORE_CASE: u32 = 1 << 0;
+/// ignore case unless the pattern has uppercase letters
+pub const MINIGREP_SMART_CASE: u32 = 1 << 1;
+pub const MINIGREP_REGEX: u32 = 1 << 2;
+/// AND / OR / NOT queries
+pub const MINIGREP_BOOLEAN: u32 = 1 << 3;
+/// matches may span lines
+pub const MINIGREP_MULTILINE: u32 = 1 << 4;
+/// NFC and NFD text match each other
+pub const MINIGREP_NORMALIZE: u32 = 1 << 5;
+/// also ignore accents
+pub const MINIGREP_FOLD_ACCENTS: u32 = 1 << 6;
+
+/// A compiled query. Opaque to C.
+pub struct MinigrepQuery {
+    searcher: Searcher,
+}
+
+/// The match records of one search. Opaque to C.
+pub struct MinigrepResults {
+    matches: Vec<MinigrepMatch>,
+}
+
+/// One match. Offsets are in bytes from the start of the searched buffer or file; the line
+/// runs from line_start to line_end, without its newline. A line that matched without a
+/// particular match (a boolean `NOT` query) gets one record with an empty match at the start
+/// of the line.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MinigrepMatch {
+    pub line_number: usize,
+    pub line_start: usize,
+    pub line_end: usize,
+    pub match_start: usize,
+    pub match_end: usize,
+}
+
+struct FfiError {
+    status: i32,
+    message: String,
+}
+
+impl FfiError {
+    fn new(status: i32, message: impl Into<String>) -> FfiError {
+        return FfiError { status, message: message.into() };
+    }
+}
+
+// Runs `body`, turning errors and panics into a status code and an error message.
+fn guard<F>(error: *mut *mut c_char, body: F) -> i32
+where
+    F: FnOnce() -> Result<(), FfiError>,
+{
+    let failure: FfiError = match panic::catch_unwind(AssertUnwindSafe(body)) {
+        Ok(Ok(())) => return MINIGREP_OK,
+        Ok(Err(failure)) => failure,
+        Err(_) => FfiError::new(MINIGREP_ERR_PANIC, "internal error (panic) in minigrep"),
+    };
+    if !error.is_null() {
+        // An interior NUL can't be represented in a C string, so drop it.
+        let message: CString = CString::new(failure.message.replace('\0', "")).unwrap_or_default();
+        // SAFETY: the caller passes either null or a valid place to store the message.
+        unsafe { *error = message.into_raw() };
+    }
+    return failure.status;
+}
+
+fn c_str<'a>(text: *const c_char, what: &str) -> Result<&'a str, FfiError> {
+    if text.is_null() {
+        return Err(FfiError::new(MINIGREP_ERR_NULL, format!("{what} is null")));
+    }
+    // SAFETY: the caller promises a NUL-terminated string that outlives the call.
+    let text: &CStr = unsafe { CStr::from_ptr(text) };
+    return text.to_str().map_err(|_| FfiError::new(MINIGREP_ERR_UTF8, format!("{what} is not UTF-8")));
+}
+
+fn search(query: &MinigrepQuery, contents: &str) -> MinigrepResults {
+    let mut matches: Vec<MinigrepMatch> = Vec::new();
+    query.searcher.search_with(contents, |line_match| {
+        let line_start: usize = line_match.line.as_ptr() as usize - contents.as_ptr() as usize;
+        let line_end: usize = line_start + line_match.line.len();
+        let record = |start: usize, end: usize| MinigrepMatch {
+            line_number: line_match.line_number,
+            line_start,
+            line_end,
+            match_start: line_start + start,
+            match_end: line_start + end,
+        };
+        matches.extend(line_match.matches.iter().map(|m| record(m.start, m.end)));
+        return true;
+    });
+    return MinigrepResults { matches };
+}
+
+fn store_results(results: MinigrepResults, out: *mut *mut MinigrepResults) {
+    // SAFETY: checked for null by the callers.
+    unsafe { *out = Box::into_raw(Box::new(results)) };
+}
+
+/// Compiles `pattern` with the MINIGREP_* `flags`.
+///
+/// # Safety
+/// `pattern` must be a NUL-terminated string, `out` a valid place for the query, and `error`
+/// null or a valid place for an error message.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn minigrep_query_new(
+    pattern: *const c_char,
+    flags: u32,
+    out: *mut *mut MinigrepQuery,
+    error: *mut *mut c_char,
+) -> i32 {
+    return guard(error, || {
+        if out.is_null() {
+            return Err(FfiError::new(MINIGREP_ERR_NULL, "out is null"));
+        }
+        let pattern: &str = c_str(pattern, "pattern")?;
+        let normalization: Normalization = if flags & MINIGREP_FOLD_ACCENTS != 0 {
+            Normalization::FoldAccents
+        } else if flags & MINIGREP_NORMALIZE != 0 {
+            Normalization::Canonical
+        } else {
+            Normalization::None
+        };
+        let searcher: Searcher = SearcherBuilder::new()
+            .pattern(pattern)
+            .ignore_case(flags & MINIGREP_IGNORE_CASE != 0)
+            .smart_case(flags & MINIGREP_SMART_CASE != 0)
+            .regex(flags & MINIGREP_REGEX != 0)
+            .boolean(flags & MINIGREP_BOOLEAN != 0)
+            .multiline(flags & MINIGREP_MULTILINE != 0)
+            .normalization(normalization)
+            .build()
+            .map_err(|e| FfiError::new(MINIGREP_ERR_QUERY, e.to_string()))?;
+        // SAFETY: checked for null above.
+        unsafe { *out = Box::into_raw(Box::new(MinigrepQuery { searcher })) };
+        return Ok(());
+    });
+}
+
+/// Frees a query. Null is ignored.
+///
+/// # Safety
+/// `query` must be null or a query from minigrep_query_new that hasn't been freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn minigrep_query_free(query: *mut MinigrepQuery) {
+    if !query.is_null() {
+        // SAFETY: the caller hands back ownership of a query we allocated.
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| drop(unsafe { Box::from_raw(query) })));
+    }
+}
+
+/// Searches `len` bytes of UTF-8 text at `buffer`.
+///
+/// # Safety
+/// `query` must be a live query, `buffer` valid for `len` bytes (it may be null if `len` is
+/// 0), `out` a valid place for the results, and `error` null or a valid place for a message.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn minigrep_search_buffer(
+    query: *const MinigrepQuery,
+    buffer: *const c_char,
+    len: usize,
+    out: *mut *mut MinigrepResults,
+    error: *mut *mut c_char,
+) -> i32 {
+    return guard(error, || {
+        if query.is_null() || out.is_null() || (buffer.is_null() && len > 0) {
+            return Err(FfiError::new(MINIGREP_ERR_NULL, "query, buffer or out is null"));
+        }
+        let bytes: &[u8] = if len == 0 {
+            &[]
+        } else {
+            // SAFETY: the caller promises `len` readable bytes at `buffer`.
+            unsafe { std::slice::from_raw_parts(buffer as *const u8, len) }
+        };
+        let contents: &str = std::str::from_utf8(bytes).map_err(|e| FfiError::new(MINIGREP_ERR_UTF8, e.to_string()))?;
+        // SAFETY: checked for null above; the caller promises it's live.
+        let query: &MinigrepQuery = unsafe { &*query };
+        store_results(search(query, contents), out);
+        return Ok(());
+    });
+}
+
+/// Reads the file at `path` and searches it. Offsets are from the start of the file.
+///
+/// # Safety
+/// As for minigrep_search_buffer, with `path` a NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn minigrep_search_file(
+    query: *const MinigrepQuery,
+    path: *const c_char,
+    out: *mut *mut MinigrepResults,
+    error: *mut *mut c_char,
+) -> i32 {
+    return guard(error, || {
+        if query.is_null() || out.is_null() {
+            return Err(FfiError::new(MINIGREP_ERR_NULL, "query or out is null"));
+        }
+        let path: &str = c_str(path, "path")?;
+        let contents: String = fs::read_to_string(path).map_err(|e| {
+            let status: i32 = if e.kind() == std::io::ErrorKind::InvalidData { MINIGREP_ERR_UTF8 } else { MINIGREP_ERR_IO };
+            FfiError::new(status, format!("{path}: {e}"))
+        })?;
+        // SAFETY: checked for null above; the caller promises it's live.
+        let query: &MinigrepQuery = unsafe { &*query };
+        store_results(search(query, &contents), out);
+        return Ok(());
+    });
+}
+
+/// The number of match records, or 0 for null.
+///
+/// # Safety
+/// `results` must be null or live results.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn minigrep_results_len(results: *const MinigrepResults) -> usize {
+    if results.is_null() {
+        return 0;
+    }
+    // SAFETY: checked for null; the caller promises it's live.
+    return unsafe { (*results).matches.len() };
+}
+
+/// Copies record `index` into `out`.
+///
+/// # Safety
+/// `results` must be live results and `out` a valid place for one record.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn minigrep_results_get(results: *const MinigrepResults, index: usize, out: *mut MinigrepMatch) -> i32 {
+    if results.is_null() || out.is_null() {
+        return MINIGREP_ERR_NULL;
+    }
+    // SAFETY: checked for null; the caller promises both are valid.
+    let results: &MinigrepResults = unsafe { &*results };
+    return match results.matches.get(index) {
+        Some(record) => {
+            unsafe { *out = *record };
+            MINIGREP_OK
+        }
+        None => MINIGREP_ERR_RANGE,
+    };
+}
+
+/// Frees search results. Null is ignored.
+///
+/// # Safety
+/// `results` must be null or results that haven't been freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn minigrep_results_free(results: *mut MinigrepResults) {
+    if !results.is_null() {
+        // SAFETY: the caller hands back ownership of results we allocated.
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| drop(unsafe { Box::from_raw(results) })));
+    }
+}
+
+/// Frees an error message. Null is ignored.
+///
+/// # Safety
+/// `text` must be null or a string returned through an `error` out-parameter.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn minigrep_string_free(text: *mut c_char) {
+    if !text.is_null() {
+        // SAFETY: the caller hands back ownership of a string we allocated.
+        drop(unsafe { CString::from_raw(text) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ptr;
+
+
+    fn query(pattern: &str, flags: u32) -> Result<*mut MinigrepQuery, String> {
+        let pattern: CString = CString::new(pattern).unwrap();
+        let mut query: *mut MinigrepQuery = ptr::null_mut();
+        let mut error: *mut c_char = ptr::null_mut();
+        let status: i32 = unsafe { minigrep_query_new(pattern.as_ptr(), flags, &mut query, &mut error) };
+        if status != MINIGREP_OK {
+            let message: String = unsafe { CStr::from_ptr(error) }.to_string_lossy().into_owned();
+            unsafe { minigrep_string_free(error) };
+            return Err(format!("{status}: {message}"));
+        }
+        return Ok(query);
+    }
+
+    fn records(query: *const MinigrepQuery, text: &str) -> Vec<MinigrepMatch> {
+        let mut results: *mut MinigrepResults = ptr::null_mut();
+        let status: i32 =
+            unsafe { minigrep_search_buffer(query, text.as_ptr() as *const c_char, text.len(), &mut results, ptr::null_mut()) };
+        assert_eq!(MINIGREP_OK, status);
+        let mut found: Vec<MinigrepMatch> = Vec::new();
+        for index in 0..unsafe { minigrep_results_len(results) } {
+            let mut record: MinigrepMatch = MinigrepMatch::default();
+            assert_eq!(MINIGREP_OK, unsafe { minigrep_results_get(results, index, &mut record) });
+            found.push(record);
+        }
+        let mut past_end: MinigrepMatch = MinigrepMatch::default();
+        assert_eq!(MINIGREP_ERR_RANGE, unsafe { minigrep_results_get(results, found.len(), &mut past_end) });
+        unsafe { minigrep_results_free(results) };
+        return found;
+    }
+
+    #[test]
+    fn searches_a_buffer() {
+        let query: *mut MinigrepQuery = query("rust", MINIGREP_IGNORE_CASE).unwrap();
+        let found: Vec<MinigrepMatch> = records(query, "Rust:\nsafe\nTrust rust.");
+        let expected: MinigrepMatch = MinigrepMatch { line_number: 3, line_start: 11, line_end: 22, match_start: 12, match_end: 16 };
+        assert_eq!(3, found.len());
+        assert_eq!(expected, found[1]);
+        assert_eq!((17, 21), (found[2].match_start, found[2].match_end));
+        unsafe { minigrep_query_free(query) };
+    }
+
+    #[test]
+    fn reports_errors_instead_of_panicking() {
+        assert!(query("(", MINIGREP_REGEX).unwrap_err().starts_with("3: "));
+        let query: *mut MinigrepQuery = query("x", 0).unwrap();
+        let mut results: *mut MinigrepResults = ptr::null_mut();
+        let bad: &[u8] = b"x\xff";
+        let status: i32 =
+            unsafe { minigrep_search_buffer(query, bad.as_ptr() as *const c_char, bad.len(), &mut results, ptr::null_mut()) };
+        assert_eq!(MINIGREP_ERR_UTF8, status);
+        let path: CString = CString::new("/no/such/file").unwrap();
+        assert_eq!(MINIGREP_ERR_IO, unsafe { minigrep_search_file(query, path.as_ptr(), &mut results, ptr::null_mut()) });
+        assert_eq!(MINIGREP_ERR_NULL, unsafe { minigrep_search_file(ptr::null(), path.as_ptr(), &mut results, ptr::null_mut()) });
+        assert_eq!(0, unsafe { minigrep_results_len(ptr::null()) });
+        unsafe { minigrep_query_free(query) };
+        unsafe { minigrep_query_free(ptr::null_mut()) };
+    }
+
+    #[test]
+    fn not_queries_get_an_empty_match() {
+        let query: *mut MinigrepQuery = query("NOT rust", MINIGREP_BOOLEAN).unwrap();
+        let expected: MinigrepMatch = MinigrepMatch { line_number: 2, line_start: 5, line_end: 9, match_start: 5, match_end: 5 };
+        assert_eq!(vec![expected], records(query, "rust\nsafe\n"));
+        unsafe { minigrep_query_free(query) };
+    }
+
+    #[test]
+    fn header_is_up_to_date() {
+        let generated: &str = include_str!(concat!(env!("OUT_DIR"), "/minigrep.h"));
+        let committed: &str = include_str!("../include/minigrep.h");
+        assert!(generated == committed, "include/minigrep.h is out of date: run `MINIGREP_UPDATE_HEADER=1 cargo build`");
+    }
+}
@@ -0,0 +1,196 @@
+// Follow mode: keep watching a growing file, like `tail -f`, and hand over each new line as it
+// is appended. The file is polled rather than watched with a platform notify API. Two things
+// are noticed on each poll:
+//
+// - truncation: the file got shorter than what was already read, so reading starts over;
+// - rotation: the path now names a different file (or none), so the old file is drained and
+//   the new one is read from the start once it appears.
+
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+// Identifies the file behind a path, to notice when it has been replaced.
+#[cfg(unix)]
+fn file_id(metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    return Some((metadata.dev(), metadata.ino()));
+}
+
+#[cfg(not(unix))]
+fn file_id(_metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    // Without inode numbers rotation can only be noticed as truncation.
+    return None;
+}
+
+pub struct Follower {
+    path: PathBuf,
+    file: Option<File>,
+    id: Option<(u64, u64)>,
+    offset: u64,
+    partial: Vec<u8>,
+    line_number: usize,
+}
+
+impl Follower {
+    /// Starts following `path` from its current end. With `count_lines` the lines already in
+    /// the file are counted, so that new lines get their real line numbers.
+    pub fn open(path: &Path, count_lines: bool) -> io::Result<Follower> {
+        let mut file: File = File::open(path)?;
+        let metadata: fs::Metadata = file.metadata()?;
+        let mut line_number: usize = 0;
+        if count_lines {
+            let mut buffer: Vec<u8> = vec![0; 64 * 1024];
+            loop {
+                let n: usize = file.read(&mut buffer)?;
+                if n == 0 {
+                    break;
+                }
+                line_number += buffer[..n].iter().filter(|&&b| b == b'\n').count();
+            }
+        }
+        let offset: u64 = file.seek(SeekFrom::End(0))?;
+        return Ok(Follower {
+            path: path.to_path_buf(),
+            file: Some(file),
+            id: file_id(&metadata),
+            offset,
+            partial: Vec::new(),
+            line_number,
+        });
+    }
+
+    /// Returns the lines completed since the last poll, with their line numbers.
+    pub fn poll(&mut self) -> io::Result<Vec<(usize, String)>> {
+        let mut lines: Vec<(usize, String)> = Vec::new();
+        let metadata: Option<fs::Metadata> = match fs::metadata(&self.path) {
+            Ok(metadata) => Some(metadata),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+        let replaced: bool = match &metadata {
+            Some(metadata) => self.file.is_none() || (self.id.is_some() && file_id(metadata) != self.id),
+            None => self.file.is_some(),
+        };
+        if replaced {
+            // Whatever was written to the old file before it was rotated still counts.
+            self.read_new(&mut lines)?;
+            self.flush_partial(&mut lines);
+            self.file = None;
+            if metadata.is_some() {
+                self.file = Some(File::open(&self.path)?);
+                self.id = metadata.as_ref().and_then(file_id);
+                self.offset = 0;
+                self.line_number = 0;
+            }
+        } else if let Some(metadata) = &metadata
+            && metadata.len() < self.offset
+        {
+            // Truncated in place, e.g. `> app.log`.
+            if let Some(file) = self.file.as_mut() {
+                file.seek(SeekFrom::Start(0))?;
+            }
+            self.offset = 0;
+            self.partial.clear();
+            self.line_number = 0;
+        }
+        self.read_new(&mut lines)?;
+        return Ok(lines);
+    }
+
+    fn read_new(&mut self, lines: &mut Vec<(usize, String)>) -> io::Result<()> {
+        let file: &mut File = match self.file.as_mut() {
+            Some(file) => file,
+            None => return Ok(()),
+        };
+        let mut buffer: Vec<u8> = Vec::new();
+        self.offset += file.read_to_end(&mut buffer)? as u64;
+        self.partial.extend_from_slice(&buffer);
+        let mut start: usize = 0;
+        while let Some(newline) = self.partial[start..].iter().position(|&b| b == b'\n') {
+            let line: &[u8] = &self.partial[start..start + newline];
+            let line: &[u8] = line.strip_suffix(b"\r").unwrap_or(line);
+            self.line_number += 1;
+            lines.push((self.line_number, String::from_utf8_lossy(line).into_owned()));
+            start += newline + 1;
+        }
+        self.partial.drain(..start);
+        return Ok(());
+    }
+
+    // A last line without a newline is only complete once its file has been rotated away.
+    fn flush_partial(&mut self, lines: &mut Vec<(usize, String)>) {
+        if !self.partial.is_empty() {
+            self.line_number += 1;
+            lines.push((self.line_number, String::from_utf8_lossy(&self.partial).into_owned()));
+            self.partial.clear();
+        }
+    }
+}
+
+/// Polls `follower` every `interval` and calls `on_line` for each new line, until `stop` is
+/// set (by a Ctrl-C handler, for example).
+pub fn follow<F>(follower: &mut Follower, interval: Duration, stop: &AtomicBool, mut on_line: F) -> io::Result<()>
+where
+    F: FnMut(usize, &str),
+{
+    while !stop.load(Ordering::SeqCst) {
+        for (line_number, line) in follower.poll()? {
+            on_line(line_number, &line);
+        }
+        sleep_unless_stopped(interval, stop);
+    }
+    return Ok(());
+}
+
+/// Sleeps for `interval`, in small steps so a stop request is noticed quickly. Returns false if
+/// `stop` was set, whether before or during the sleep.
+pub fn sleep_unless_stopped(interval: Duration, stop: &AtomicBool) -> bool {
+    let mut slept: Duration = Duration::ZERO;
+    while slept < interval && !stop.load(Ordering::SeqCst) {
+        let step: Duration = (interval - slept).min(Duration::from_millis(50));
+        thread::sleep(step);
+        slept += step;
+    }
+    return !stop.load(Ordering::SeqCst);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    use crate::test_util::scratch_dir;
+
+    fn lines(follower: &mut Follower) -> Vec<(usize, String)> {
+        return follower.poll().unwrap();
+    }
+
+    #[test]
+    fn appends_truncation_and_rotation() {
+        let dir: PathBuf = scratch_dir("follow", &[("app.log", "old 1\nold 2\n")]);
+        let path: PathBuf = dir.join("app.log");
+
+        let mut follower: Follower = Follower::open(&path, true).unwrap();
+        assert!(lines(&mut follower).is_empty());
+        let mut file: File = fs::OpenOptions::new().append(true).open(&path).unwrap();
+        write!(file, "new ERROR\npart").unwrap();
+        assert_eq!(vec![(3, String::from("new ERROR"))], lines(&mut follower));
+        writeln!(file, "ial").unwrap();
+        assert_eq!(vec![(4, String::from("partial"))], lines(&mut follower));
+
+        fs::write(&path, "x\n").unwrap();
+        assert_eq!(vec![(1, String::from("x"))], lines(&mut follower));
+
+        write!(file, "last words").unwrap();
+        fs::rename(&path, dir.join("app.log.1")).unwrap();
+        fs::write(&path, "fresh\n").unwrap();
+        let found: Vec<(usize, String)> = lines(&mut follower);
+        assert_eq!(String::from("fresh"), found.last().unwrap().1);
+        assert_eq!(1, found.last().unwrap().0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,269 @@
+// Glob patterns for choosing which files a directory search looks at, and the named file types
+// (`-t rust`) built on top of them.
+//
+// A glob without a slash is matched against the file name only, so `*.rs` finds Rust files at
+// any depth. A glob with a slash is matched against the path relative to the searched directory.
+// `*` and `?` never match a slash, `**` matches any number of directories, and `[abc]`,
+// `[!abc]` and `{a,b}` work as they do in a shell.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::Path;
+
+use regex::{Regex, RegexBuilder};
+
+#[derive(Debug, Clone)]
+pub struct Glob {
+    glob: String,
+    regex: Regex,
+    basename_only: bool,
+}
+
+impl Glob {
+    pub fn new(glob: &str, ignore_case: bool) -> Result<Glob, Box<dyn Error>> {
+        let basename_only: bool = !glob.contains('/');
+        let pattern: String = to_regex(glob.strip_prefix('/').unwrap_or(glob))
+            .map_err(|e| format!("invalid glob `{glob}`: {e}"))?;
+        let regex: Regex = RegexBuilder::new(&pattern).case_insensitive(ignore_case).build()?;
+        return Ok(Glob { glob: glob.to_string(), regex, basename_only });
+    }
+
+    pub fn as_str(&self) -> &str {
+        return &self.glob;
+    }
+
+    /// Matches `relative`, a path relative to the directory being searched.
+    pub fn is_match(&self, relative: &Path) -> bool {
+        if self.basename_only {
+            return match relative.file_name() {
+                Some(name) => self.regex.is_match(&name.to_string_lossy()),
+                None => false,
+            };
+        }
+        // Always compare with forward slashes so the same glob works on every platform.
+        let path: String = relative.to_string_lossy().replace('\\', "/");
+        return self.regex.is_match(&path);
+    }
+}
+
+fn to_regex(glob: &str) -> Result<String, &'static str> {
+    let mut regex: String = String::from("^");
+    let chars: Vec<char> = glob.chars().collect();
+    let mut i: usize = 0;
+    let mut braces: usize = 0;
+    while i < chars.len() {
+        let c: char = chars[i];
+        match c {
+            '*' if chars.get(i + 1) == Some(&'*') => {
+                let at_start: bool = i == 0 || chars[i - 1] == '/';
+                if at_start && chars.get(i + 2) == Some(&'/') {
+                    regex.push_str("(?:.*/)?");
+                    i += 3;
+                } else {
+                    regex.push_str(".*");
+                    i += 2;
+                }
+                continue;
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '[' => {
+                let close: usize = match chars[i + 1..].iter().skip(1).position(|&c| c == ']') {
+                    Some(offset) => i + 2 + offset,
+                    None => return Err("unclosed '['"),
+                };
+                regex.push('[');
+                let mut class: &[char] = &chars[i + 1..close];
+                if let Some('!') | Some('^') = class.first() {
+                    regex.push('^');
+                    class = &class[1..];
+                }
+                // `&&`, `~~` and `--` are set operators in a regex class, so `&` and `~` are
+                // always escaped and `-` is only left alone when it's a range between two
+                // other characters.
+                for (j, c) in class.iter().enumerate() {
+                    let range: bool = *c == '-' && j > 0 && j + 1 < class.len() && class[j - 1] != '-' && class[j + 1] != '-';
+                    if matches!(c, '\\' | '[' | ']' | '&' | '~') || (*c == '-' && !range) {
+                        regex.push('\\');
+                    }
+                    regex.push(*c);
+                }
+                regex.push(']');
+                i = close + 1;
+                continue;
+            }
+            '{' => {
+                braces += 1;
+                regex.push_str("(?:");
+            }
+            '}' if braces > 0 => {
+                braces -= 1;
+                regex.push(')');
+            }
+            ',' if braces > 0 => regex.push('|'),
+            _ => regex.push_str(&regex::escape(&c.to_string())),
+        }
+        i += 1;
+    }
+    if braces > 0 {
+        return Err("unclosed '{'");
+    }
+    regex.push('$');
+    return Ok(regex);
+}
+
+/// The built-in file types. More can be added with `--type-add NAME:GLOB[,GLOB...]`.
+const DEFAULT_TYPES: &[(&str, &[&str])] = &[
+    ("c", &["*.c", "*.h"]),
+    ("cpp", &["*.cpp", "*.cc", "*.cxx", "*.hpp", "*.hh", "*.h"]),
+    ("csv", &["*.csv", "*.tsv"]),
+    ("go", &["*.go"]),
+    ("html", &["*.html", "*.htm"]),
+    ("java", &["*.java"]),
+    ("js", &["*.js", "*.mjs", "*.cjs"]),
+    ("json", &["*.json", "*.jsonl"]),
+    ("log", &["*.log"]),
+    ("markdown", &["*.md", "*.markdown"]),
+    ("python", &["*.py"]),
+    ("rust", &["*.rs"]),
+    ("sh", &["*.sh", "*.bash"]),
+    ("toml", &["*.toml", "Cargo.lock"]),
+    ("ts", &["*.ts", "*.tsx"]),
+    ("txt", &["*.txt"]),
+    ("yaml", &["*.yaml", "*.yml"]),
+];
+
+#[derive(Debug, Clone)]
+pub struct FileTypes {
+    types: BTreeMap<String, Vec<String>>,
+}
+
+impl Default for FileTypes {
+    fn default() -> FileTypes {
+        let mut types: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for (name, globs) in DEFAULT_TYPES {
+            types.insert(name.to_string(), globs.iter().map(|g| g.to_string()).collect());
+        }
+        return FileTypes { types };
+    }
+}
+
+impl FileTypes {
+    /// Adds globs to a type, creating it if needed. `definition` looks like `web:*.html,*.css`.
+    pub fn add(&mut self, definition: &str) -> Result<(), Box<dyn Error>> {
+        let (name, globs) = match definition.split_once(':') {
+            Some((name, globs)) if !name.is_empty() && !globs.is_empty() => (name, globs),
+            _ => return Err(format!("invalid type definition `{definition}`, expected NAME:GLOB").into()),
+        };
+        let entry: &mut Vec<String> = self.types.entry(name.to_string()).or_default();
+        for glob in globs.split(',') {
+            entry.push(glob.to_string());
+        }
+        return Ok(());
+    }
+
+    pub fn globs(&self, name: &str) -> Result<Vec<Glob>, Box<dyn Error>> {
+        let globs: &Vec<String> = self.types.get(name).ok_or_else(|| format!("unknown file type `{name}`"))?;
+        return globs.iter().map(|g| Glob::new(g, false)).collect();
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &[String])> {
+        return self.types.iter().map(|(name, globs)| (name.as_str(), globs.as_slice()));
+    }
+}
+
+/// Decides which files a directory search looks at. A file must match one of the include
+/// globs (if there are any), one of the selected types (if any are selected), and none of the
+/// exclude globs or excluded types.
+#[derive(Debug, Clone, Default)]
+pub struct FileFilter {
+    include: Vec<Glob>,
+    exclude: Vec<Glob>,
+    types: Vec<Glob>,
+    types_not: Vec<Glob>,
+}
+
+impl FileFilter {
+    /// Adds a glob. One starting with `!` excludes the files it matches.
+    pub fn add_glob(&mut self, glob: &str, ignore_case: bool) -> Result<(), Box<dyn Error>> {
+        match glob.strip_prefix('!') {
+            Some(glob) => self.exclude.push(Glob::new(glob, ignore_case)?),
+            None => self.include.push(Glob::new(glob, ignore_case)?),
+        }
+        return Ok(());
+    }
+
+    pub fn select_type(&mut self, types: &FileTypes, name: &str) -> Result<(), Box<dyn Error>> {
+        self.types.extend(types.globs(name)?);
+        return Ok(());
+    }
+
+    pub fn reject_type(&mut self, types: &FileTypes, name: &str) -> Result<(), Box<dyn Error>> {
+        self.types_not.extend(types.globs(name)?);
+        return Ok(());
+    }
+
+    /// Whether a directory should be descended into. Only excludes apply, so `!target` skips
+    /// the whole `target` directory.
+    pub fn allows_dir(&self, relative: &Path) -> bool {
+        return !self.exclude.iter().any(|g| g.is_match(relative));
+    }
+
+    pub fn allows_file(&self, relative: &Path) -> bool {
+        if self.exclude.iter().any(|g| g.is_match(relative)) || self.types_not.iter().any(|g| g.is_match(relative)) {
+            return false;
+        }
+        if !self.include.is_empty() && !self.include.iter().any(|g| g.is_match(relative)) {
+            return false;
+        }
+        return self.types.is_empty() || self.types.iter().any(|g| g.is_match(relative));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_match(glob: &str, path: &str) -> bool {
+        return Glob::new(glob, false).unwrap().is_match(Path::new(path));
+    }
+
+    #[test]
+    fn glob_syntax() {
+        assert!(is_match("*.rs", "src/lib.rs"));
+        assert!(!is_match("src/*.rs", "src/bin/main.rs"));
+        assert!(is_match("src/**/*.rs", "src/bin/main.rs"));
+        assert!(is_match("src/**/*.rs", "src/lib.rs"));
+        assert!(is_match("**/poem.txt", "poem.txt"));
+        assert!(is_match("file?.[!a-c]{rs,toml}", "file1.drs"));
+        assert!(!is_match("file?.[!a-c]{rs,toml}", "file1.arust"));
+        assert!(Glob::new("*.LOCK", true).unwrap().is_match(Path::new("Cargo.lock")));
+        assert!(Glob::new("{a,b", false).is_err());
+    }
+
+    #[test]
+    fn classes_are_taken_literally() {
+        assert!(is_match("[a&&b].txt", "&.txt"));
+        assert!(is_match("[a&&b].txt", "a.txt"));
+        assert!(is_match("[a~~b].txt", "~.txt"));
+        assert!(is_match("[a--b].txt", "-.txt"));
+        assert!(!is_match("[a--b].txt", "c.txt"));
+        assert!(is_match("[-x].txt", "-.txt"));
+        assert!(is_match("[a-c].txt", "b.txt"));
+    }
+
+    #[test]
+    fn filter_combines_globs_and_types() {
+        let mut types: FileTypes = FileTypes::default();
+        types.add("poems:*.txt,*.poem").unwrap();
+        let mut filter: FileFilter = FileFilter::default();
+        filter.select_type(&types, "rust").unwrap();
+        filter.select_type(&types, "poems").unwrap();
+        filter.add_glob("!*_test.rs", false).unwrap();
+        assert!(filter.allows_file(Path::new("src/lib.rs")));
+        assert!(filter.allows_file(Path::new("poem.txt")));
+        assert!(!filter.allows_file(Path::new("src/lib_test.rs")));
+        assert!(!filter.allows_file(Path::new("Cargo.lock")));
+        assert!(filter.select_type(&types, "cobol").is_err());
+    }
+}
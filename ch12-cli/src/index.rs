@@ -0,0 +1,357 @@
+// A persistent trigram index for searching the same directory over and over. Every file is
+// broken into lowercased three-character windows, and the index maps each trigram to the files
+// that contain it. A query can only match a file that contains all of the query's trigrams, so
+// the index narrows the search to a few candidate files, which are then searched normally.
+//
+// The index lives in a single file at the root of the indexed directory:
+//
+//   magic "MGREPIDX", format version (u32)
+//   file count (u32), then per file: path, mtime seconds (u64), mtime nanos (u32), size (u64)
+//   trigram count (u32), then per trigram: trigram (u64), posting count (u32), file ids (u32...)
+//   FNV-1a checksum (u64) of everything after the version
+//
+// All integers are little endian and paths are a u32 length followed by UTF-8 bytes.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::walk;
+
+pub const INDEX_FILE_NAME: &str = ".minigrep.idx";
+
+const MAGIC: &[u8; 8] = b"MGREPIDX";
+const VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub enum IndexError {
+    Io(io::Error),
+    BadMagic,
+    UnsupportedVersion(u32),
+    Corrupt(&'static str),
+}
+
+impl fmt::Display for IndexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            IndexError::Io(e) => write!(f, "index I/O error: {e}"),
+            IndexError::BadMagic => write!(f, "not a minigrep index file"),
+            IndexError::UnsupportedVersion(v) => write!(f, "unsupported index version {v} (expected {VERSION})"),
+            IndexError::Corrupt(why) => write!(f, "index file is corrupt: {why}"),
+        };
+    }
+}
+
+impl Error for IndexError {}
+
+impl From<io::Error> for IndexError {
+    fn from(e: io::Error) -> IndexError {
+        return IndexError::Io(e);
+    }
+}
+
+/// What a cache remembers about a file to tell whether it has changed since: its mtime and
+/// size. The index and rank mode's cache both use it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FileStamp {
+    pub mtime_secs: u64,
+    pub mtime_nanos: u32,
+    pub size: u64,
+}
+
+impl FileStamp {
+    pub fn of(path: &Path) -> io::Result<FileStamp> {
+        let metadata: fs::Metadata = fs::metadata(path)?;
+        let (mtime_secs, mtime_nanos) = match metadata.modified()?.duration_since(UNIX_EPOCH) {
+            Ok(d) => (d.as_secs(), d.subsec_nanos()),
+            Err(_) => (0, 0),
+        };
+        return Ok(FileStamp { mtime_secs, mtime_nanos, size: metadata.len() });
+    }
+}
+
+/// Writes `contents` to a temporary file next to `path` and renames it into place, so an
+/// interrupted save never leaves a torn file behind.
+pub fn write_atomically(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut tmp: std::ffi::OsString = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    fs::write(&tmp, contents)?;
+    fs::rename(&tmp, path)?;
+    return Ok(());
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct IndexedFile {
+    path: String,
+    stamp: FileStamp,
+    trigrams: BTreeSet<u64>,
+}
+
+/// How many files an update looked at and how many it had to re-read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct UpdateSummary {
+    pub files: usize,
+    pub reindexed: usize,
+    pub removed: usize,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Index {
+    files: Vec<IndexedFile>,
+}
+
+impl Index {
+    pub fn index_path(root: &Path) -> PathBuf {
+        return root.join(INDEX_FILE_NAME);
+    }
+
+    /// Loads the index for `root`, or starts an empty one if there is none yet.
+    pub fn open(root: &Path) -> Result<Index, IndexError> {
+        let bytes: Vec<u8> = match fs::read(Index::index_path(root)) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Index::default()),
+            Err(e) => return Err(e.into()),
+        };
+        return Index::decode(&bytes);
+    }
+
+    pub fn save(&self, root: &Path) -> Result<(), IndexError> {
+        write_atomically(&Index::index_path(root), &self.encode())?;
+        return Ok(());
+    }
+
+    /// Brings the index up to date with the files under `root`. Files whose mtime and size
+    /// haven't changed keep their entries; everything else is read again.
+    pub fn update(&mut self, root: &Path) -> Result<UpdateSummary, IndexError> {
+        let mut previous: HashMap<String, IndexedFile> =
+            self.files.drain(..).map(|f| (f.path.clone(), f)).collect();
+        let mut summary: UpdateSummary = UpdateSummary::default();
+        for path in walk::walk(root)? {
+            let relative: String = path.strip_prefix(root).unwrap_or(&path).to_string_lossy().into_owned();
+            let stamp: FileStamp = FileStamp::of(&path)?;
+            summary.files += 1;
+            if let Some(old) = previous.remove(&relative)
+                && old.stamp == stamp
+            {
+                self.files.push(old);
+                continue;
+            }
+            let contents: String = match walk::read_text(&path, false) {
+                Ok(contents) => contents,
+                // Binary files are never searched, so index them as having no trigrams.
+                Err(e) if e.kind() == io::ErrorKind::InvalidData => String::new(),
+                Err(e) => return Err(e.into()),
+            };
+            summary.reindexed += 1;
+            self.files.push(IndexedFile { path: relative, stamp, trigrams: trigrams(&contents) });
+        }
+        summary.removed = previous.len();
+        self.files.sort_by(|a, b| a.path.cmp(&b.path));
+        return Ok(summary);
+    }
+
+    /// Returns the files under `root` that could contain `query`. Queries shorter than a
+    /// trigram can't be narrowed down, so every file is a candidate.
+    pub fn candidates(&self, root: &Path, query: &str) -> Vec<PathBuf> {
+        return self.candidates_by(root, |may_contain| may_contain(query));
+    }
+
+    /// Like `candidates`, but `keep` decides for each file, given a test for whether a string
+    /// might occur in that file. This is how queries made of several terms use the index.
+    pub fn candidates_by<F>(&self, root: &Path, keep: F) -> Vec<PathBuf>
+    where
+        F: Fn(&dyn Fn(&str) -> bool) -> bool,
+    {
+        return self
+            .files
+            .iter()
+            .filter(|f| keep(&|text: &str| trigrams(text).is_subset(&f.trigrams)))
+            .map(|f| root.join(&f.path))
+            .collect();
+    }
+
+    pub fn len(&self) -> usize {
+        return self.files.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.files.is_empty();
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut postings: BTreeMap<u64, Vec<u32>> = BTreeMap::new();
+        for (id, file) in self.files.iter().enumerate() {
+            for trigram in &file.trigrams {
+                postings.entry(*trigram).or_default().push(id as u32);
+            }
+        }
+        let mut body: Vec<u8> = Vec::new();
+        body.extend_from_slice(&(self.files.len() as u32).to_le_bytes());
+        for file in &self.files {
+            body.extend_from_slice(&(file.path.len() as u32).to_le_bytes());
+            body.extend_from_slice(file.path.as_bytes());
+            body.extend_from_slice(&file.stamp.mtime_secs.to_le_bytes());
+            body.extend_from_slice(&file.stamp.mtime_nanos.to_le_bytes());
+            body.extend_from_slice(&file.stamp.size.to_le_bytes());
+        }
+        body.extend_from_slice(&(postings.len() as u32).to_le_bytes());
+        for (trigram, ids) in &postings {
+            body.extend_from_slice(&trigram.to_le_bytes());
+            body.extend_from_slice(&(ids.len() as u32).to_le_bytes());
+            for id in ids {
+                body.extend_from_slice(&id.to_le_bytes());
+            }
+        }
+        let mut bytes: Vec<u8> = Vec::with_capacity(body.len() + 20);
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&body);
+        bytes.extend_from_slice(&fnv1a(&body).to_le_bytes());
+        return bytes;
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Index, IndexError> {
+        if bytes.len() < MAGIC.len() || &bytes[..MAGIC.len()] != MAGIC {
+            return Err(IndexError::BadMagic);
+        }
+        let mut reader: Reader = Reader { bytes, pos: MAGIC.len() };
+        let version: u32 = reader.u32()?;
+        if version != VERSION {
+            return Err(IndexError::UnsupportedVersion(version));
+        }
+        if bytes.len() < reader.pos + 8 {
+            return Err(IndexError::Corrupt("file is truncated"));
+        }
+        let body_end: usize = bytes.len() - 8;
+        let stored: u64 = u64::from_le_bytes(bytes[body_end..].try_into().unwrap());
+        if fnv1a(&bytes[reader.pos..body_end]) != stored {
+            return Err(IndexError::Corrupt("checksum mismatch"));
+        }
+        reader.bytes = &bytes[..body_end];
+
+        let mut files: Vec<IndexedFile> = Vec::new();
+        for _ in 0..reader.u32()? {
+            let path: String = reader.string()?;
+            let stamp: FileStamp = FileStamp { mtime_secs: reader.u64()?, mtime_nanos: reader.u32()?, size: reader.u64()? };
+            files.push(IndexedFile { path, stamp, trigrams: BTreeSet::new() });
+        }
+        for _ in 0..reader.u32()? {
+            let trigram: u64 = reader.u64()?;
+            for _ in 0..reader.u32()? {
+                let id: usize = reader.u32()? as usize;
+                match files.get_mut(id) {
+                    Some(file) => file.trigrams.insert(trigram),
+                    None => return Err(IndexError::Corrupt("posting refers to an unknown file")),
+                };
+            }
+        }
+        if reader.pos != reader.bytes.len() {
+            return Err(IndexError::Corrupt("trailing data"));
+        }
+        return Ok(Index { files });
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, n: usize) -> Result<&[u8], IndexError> {
+        let end: usize = self.pos.checked_add(n).ok_or(IndexError::Corrupt("length overflow"))?;
+        let slice: &[u8] = self.bytes.get(self.pos..end).ok_or(IndexError::Corrupt("file is truncated"))?;
+        self.pos = end;
+        return Ok(slice);
+    }
+
+    fn u32(&mut self) -> Result<u32, IndexError> {
+        return Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()));
+    }
+
+    fn u64(&mut self) -> Result<u64, IndexError> {
+        return Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()));
+    }
+
+    fn string(&mut self) -> Result<String, IndexError> {
+        let len: usize = self.u32()? as usize;
+        let bytes: Vec<u8> = self.take(len)?.to_vec();
+        return String::from_utf8(bytes).map_err(|_| IndexError::Corrupt("path is not UTF-8"));
+    }
+}
+
+/// The lowercased trigrams of `text`, each packed into a u64 (21 bits per char).
+fn trigrams(text: &str) -> BTreeSet<u64> {
+    let chars: Vec<char> = text.chars().flat_map(char::to_lowercase).collect();
+    let mut set: BTreeSet<u64> = BTreeSet::new();
+    for window in chars.windows(3) {
+        set.insert(((window[0] as u64) << 42) | ((window[1] as u64) << 21) | window[2] as u64);
+    }
+    return set;
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    return hash;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::scratch_dir;
+
+    #[test]
+    fn candidates_need_every_trigram() {
+        let files: [(&str, &str); 3] = [("a.txt", "safe, fast, productive."), ("b.txt", "Pick three."), ("c.bin", "productive\0\0\0")];
+        let dir: PathBuf = scratch_dir("index-candidates", &files);
+        let mut index: Index = Index::default();
+        index.update(&dir).unwrap();
+        assert_eq!(vec![dir.join("a.txt")], index.candidates(&dir, "DUCT"));
+        assert_eq!(3, index.candidates(&dir, "e").len());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn round_trip_and_incremental_update() {
+        let dir: PathBuf = scratch_dir("index-roundtrip", &[("a.txt", "Rust"), ("b.txt", "Trust me.")]);
+        let mut index: Index = Index::default();
+        assert_eq!(2, index.update(&dir).unwrap().reindexed);
+        index.save(&dir).unwrap();
+
+        let mut loaded: Index = Index::open(&dir).unwrap();
+        assert_eq!(index, loaded);
+        fs::write(dir.join("b.txt"), "Pick three, trust me.").unwrap();
+        fs::remove_file(dir.join("a.txt")).unwrap();
+        let summary: UpdateSummary = loaded.update(&dir).unwrap();
+        assert_eq!(1, summary.reindexed);
+        assert_eq!(1, summary.removed);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn detects_corruption() {
+        let mut index: Index = Index::default();
+        index.files.push(IndexedFile {
+            path: "poem.txt".to_string(),
+            stamp: FileStamp { mtime_secs: 1, mtime_nanos: 2, size: 3 },
+            trigrams: trigrams("nobody"),
+        });
+        let mut bytes: Vec<u8> = index.encode();
+        assert_eq!(index, Index::decode(&bytes).unwrap());
+
+        bytes[20] ^= 0xff;
+        assert!(matches!(Index::decode(&bytes), Err(IndexError::Corrupt(_))));
+        assert!(matches!(Index::decode(&bytes[..10]), Err(IndexError::Corrupt(_))));
+        assert!(matches!(Index::decode(b"not an index"), Err(IndexError::BadMagic)));
+        bytes[8] = 9;
+        assert!(matches!(Index::decode(&bytes), Err(IndexError::UnsupportedVersion(9))));
+    }
+}
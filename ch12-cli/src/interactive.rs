@@ -0,0 +1,261 @@
+// Interactive mode: load a file once, then type queries at a prompt instead of restarting
+// minigrep for each one. A line is a query unless it starts with a colon:
+//
+//   :i          toggle case-insensitive matching
+//   :n          toggle line numbers
+//   :open FILE  search a different file
+//   :history    list this session's queries
+//   :N          run query number N from the history again
+//   :help       list the commands
+//   :q          quit (so does end of input)
+//
+// A query that really starts with a colon is written with two (`::foo` searches for `:foo`).
+// Results longer than a page stop after each page until Enter is pressed.
+
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::io::{self, BufRead, Write};
+
+use crate::Config;
+use crate::searcher::Searcher;
+use crate::stats::Stats;
+
+const DEFAULT_PAGE_SIZE: usize = 20;
+
+const HELP: &str = "\
+Type a query to search the file, or one of:
+  :i          toggle case-insensitive matching
+  :n          toggle line numbers
+  :open FILE  search a different file
+  :history    list this session's queries
+  :N          run query number N again
+  :q          quit";
+
+pub struct Session {
+    config: Config,
+    contents: String,
+    history: Vec<String>,
+    /// How many result lines to show before pausing.
+    pub page_size: usize,
+}
+
+impl Session {
+    /// Loads the file named by `config`. The page size follows the terminal height in `LINES`
+    /// when the shell exports it.
+    pub fn open(config: Config) -> Result<Session, Box<dyn Error>> {
+        let contents: String = read_file(&config.file_path)?;
+        let page_size: usize = env::var("LINES")
+            .ok()
+            .and_then(|lines| lines.parse::<usize>().ok())
+            .map(|lines| lines.saturating_sub(2).max(1))
+            .unwrap_or(DEFAULT_PAGE_SIZE);
+        return Ok(Session { config, contents, history: Vec::new(), page_size });
+    }
+
+    /// The queries run so far, oldest first.
+    pub fn history(&self) -> &[String] {
+        return &self.history;
+    }
+
+    /// Reads queries and commands from `input` until `:q` or the end of input. A query given
+    /// on the command line is run first.
+    pub fn run<R: BufRead, W: Write>(&mut self, mut input: R, mut output: W) -> Result<(), Box<dyn Error>> {
+        writeln!(output, "Searching {} (:help for commands)", self.config.file_path)?;
+        if !self.config.query.is_empty() {
+            let query: String = self.config.query.clone();
+            self.query(&query, &mut input, &mut output)?;
+        }
+        loop {
+            write!(output, "minigrep> ")?;
+            output.flush()?;
+            let line: String = match read_line(&mut input)? {
+                Some(line) => line,
+                None => {
+                    writeln!(output)?;
+                    return Ok(());
+                }
+            };
+            let line: &str = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let command: &str = match line.strip_prefix(':') {
+                Some(rest) if !rest.starts_with(':') => rest.trim(),
+                Some(rest) => {
+                    self.query(rest, &mut input, &mut output)?;
+                    continue;
+                }
+                None => {
+                    self.query(line, &mut input, &mut output)?;
+                    continue;
+                }
+            };
+            match command.split_once(' ').map(|(name, rest)| (name, rest.trim())).unwrap_or((command, "")) {
+                ("q" | "quit", _) => return Ok(()),
+                ("i", _) => {
+                    // An explicit toggle replaces smart case, as -i does on the command line.
+                    self.config.ignore_case = !self.config.ignore_case;
+                    self.config.smart_case = false;
+                    writeln!(output, "case-insensitive: {}", on_off(self.config.ignore_case))?;
+                }
+                ("n", _) => {
+                    self.config.line_number = !self.config.line_number;
+                    writeln!(output, "line numbers: {}", on_off(self.config.line_number))?;
+                }
+                ("open", "") => writeln!(output, ":open needs a file name")?,
+                ("open", path) => match read_file(path) {
+                    Ok(contents) => {
+                        writeln!(output, "Opened {path} ({} lines)", contents.lines().count())?;
+                        self.config.file_path = path.to_string();
+                        self.contents = contents;
+                    }
+                    Err(e) => writeln!(output, "{e}")?,
+                },
+                ("history", _) => {
+                    for (number, query) in self.history.iter().enumerate() {
+                        writeln!(output, "{:>4}  {query}", number + 1)?;
+                    }
+                }
+                ("help", _) => writeln!(output, "{HELP}")?,
+                (number, "") if number.parse::<usize>().is_ok() => {
+                    let number: usize = number.parse().unwrap();
+                    match number.checked_sub(1).and_then(|i| self.history.get(i)).cloned() {
+                        Some(query) => {
+                            writeln!(output, "{query}")?;
+                            self.query(&query, &mut input, &mut output)?;
+                        }
+                        None => writeln!(output, "no query number {number} in the history")?,
+                    }
+                }
+                _ => writeln!(output, "unknown command `:{command}` (:help lists them)")?,
+            }
+        }
+    }
+
+    // Runs one query against the loaded file and pages through the results. A bad query
+    // (an invalid regex, say) is reported without ending the session.
+    fn query<R: BufRead, W: Write>(&mut self, query: &str, input: &mut R, output: &mut W) -> Result<(), Box<dyn Error>> {
+        if self.history.last().map(|last| last.as_str()) != Some(query) {
+            self.history.push(query.to_string());
+        }
+        let mut config: Config = self.config.clone();
+        config.query = query.to_string();
+        let searcher: Searcher = match config.searcher() {
+            Ok(searcher) => searcher,
+            Err(e) => {
+                writeln!(output, "{e}")?;
+                return Ok(());
+            }
+        };
+        let mut results: String = String::new();
+        let stats: Stats = match crate::search_contents(&config, &searcher, None, &self.contents, 0, &mut |found: &str| results.push_str(found)) {
+            Ok(stats) => stats,
+            Err(e) => {
+                writeln!(output, "{e}")?;
+                return Ok(());
+            }
+        };
+        // Results end in a NUL with -z, so they're split on whatever they end with.
+        let end: &str = config.result_end();
+        let lines: Vec<&str> = results.split_terminator(end).collect();
+        for (shown, line) in lines.iter().enumerate() {
+            if shown > 0 && shown % self.page_size == 0 {
+                write!(output, "-- {} more lines: Enter for more, q to stop --", lines.len() - shown)?;
+                output.flush()?;
+                let answer: Option<String> = read_line(input)?;
+                if answer.is_none_or(|answer| answer.trim() == "q") {
+                    break;
+                }
+            }
+            write!(output, "{line}{end}")?;
+        }
+        writeln!(output, "({} matching lines)", stats.matched_lines)?;
+        return Ok(());
+    }
+}
+
+fn read_file(path: &str) -> Result<String, Box<dyn Error>> {
+    return fs::read_to_string(path).map_err(|e| format!("{path}: {e}").into());
+}
+
+fn read_line<R: BufRead>(input: &mut R) -> io::Result<Option<String>> {
+    let mut line: String = String::new();
+    if input.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    return Ok(Some(line));
+}
+
+fn on_off(on: bool) -> &'static str {
+    return if on { "on" } else { "off" };
+}
+
+/// Runs an interactive session on the terminal.
+pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
+    let mut session: Session = Session::open(config)?;
+    return session.run(io::stdin().lock(), io::stdout().lock());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    use crate::test_util::scratch_dir;
+
+    fn session(name: &str, contents: &str) -> (Session, PathBuf) {
+        let dir: PathBuf = scratch_dir(&format!("interactive-{name}"), &[("input.txt", contents)]);
+        let config: Config = Config { file_path: dir.join("input.txt").display().to_string(), ..Config::default() };
+        return (Session::open(config).unwrap(), dir);
+    }
+
+    fn transcript(session: &mut Session, input: &str) -> String {
+        let mut output: Vec<u8> = Vec::new();
+        session.run(input.as_bytes(), &mut output).unwrap();
+        return String::from_utf8(output).unwrap();
+    }
+
+    #[test]
+    fn toggles_and_history() {
+        let (mut session, dir) = session("toggles", "Rust\nrust\ntrust\n");
+        let output: String = transcript(&mut session, "Rust\n:i\n:n\nRUST\n:1\n:history\n");
+        assert!(output.contains("case-insensitive: on"));
+        assert!(output.contains("1:Rust\n2:rust\n3:trust\n(3 matching lines)"));
+        assert!(output.contains("   1  Rust\n   2  RUST\n"));
+        assert_eq!(vec!["Rust", "RUST", "Rust"], session.history());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn pages_long_results() {
+        let (mut session, dir) = session("paging", "x\n".repeat(5).as_str());
+        session.page_size = 2;
+        let output: String = transcript(&mut session, "x\n\nq\n:q\n");
+        assert_eq!(4, output.matches("x\n").count());
+        assert!(output.contains("-- 3 more lines") && output.contains("-- 1 more lines"));
+        assert!(output.contains("(5 matching lines)"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn pages_nul_separated_records() {
+        let (mut session, dir) = session("nul", "x one\nx two\0x three\0y\0x four\0");
+        session.config.terminator = crate::records::Terminator::Separator(String::from("\0"));
+        session.page_size = 2;
+        let output: String = transcript(&mut session, "x\n\n:q\n");
+        assert!(output.contains("x one\nx two\0x three\0-- 1 more lines"), "{output:?}");
+        assert!(output.contains("x four\0(3 matching lines)"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reports_errors_and_keeps_going() {
+        let (mut session, dir) = session("errors", "a:b\n");
+        let output: String = transcript(&mut session, ":open /no/such/file\n:bogus\n::b\n");
+        assert!(output.contains("/no/such/file: "));
+        assert!(output.contains("unknown command `:bogus`"));
+        assert!(output.contains("a:b\n(1 matching lines)"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
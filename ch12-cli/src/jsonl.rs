@@ -0,0 +1,166 @@
+// Field-scoped search for JSON Lines files, where every line is one JSON object. Instead of
+// matching the whole line, the query is matched against the values at some field paths:
+//
+//   level              a top-level field
+//   request.headers.ua a nested object
+//   items.0.name       an array element, by index
+//   items.*.name       every element of an array (or every value of an object)
+//
+// Strings are matched as they are; other values are matched as their JSON text. Lines that
+// aren't valid JSON are collected rather than stopping the search.
+
+use serde_json::Value;
+
+use crate::matcher::Matcher;
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct JsonlOptions {
+    /// Field paths to match the query against; the whole line if empty.
+    pub fields: Vec<String>,
+    /// Field paths to print for matching lines; the whole line if empty.
+    pub print_fields: Vec<String>,
+}
+
+/// Every value at `path` inside `value`. A `*` segment fans out over arrays and objects.
+pub fn lookup<'v>(value: &'v Value, path: &str) -> Vec<&'v Value> {
+    let mut current: Vec<&'v Value> = vec![value];
+    for segment in path.split('.').filter(|s| !s.is_empty()) {
+        let mut next: Vec<&'v Value> = Vec::new();
+        for value in current {
+            match (value, segment) {
+                (Value::Array(items), "*") => next.extend(items.iter()),
+                (Value::Object(fields), "*") => next.extend(fields.values()),
+                (Value::Array(items), index) => {
+                    if let Some(item) = index.parse::<usize>().ok().and_then(|i| items.get(i)) {
+                        next.push(item);
+                    }
+                }
+                (Value::Object(fields), key) => {
+                    if let Some(field) = fields.get(key) {
+                        next.push(field);
+                    }
+                }
+                _ => {}
+            }
+        }
+        current = next;
+    }
+    return current;
+}
+
+fn value_text(value: &Value) -> String {
+    return match value {
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    };
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsonlMatch<'a> {
+    pub line_number: usize,
+    pub raw: &'a str,
+    pub matches: usize,
+    pub output: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct JsonlResult<'a> {
+    pub lines_scanned: usize,
+    pub matches: Vec<JsonlMatch<'a>>,
+    /// Line numbers and parse errors of the lines that weren't valid JSON.
+    pub invalid: Vec<(usize, String)>,
+}
+
+pub fn search<'a>(matcher: &dyn Matcher, text: &'a str, options: &JsonlOptions) -> JsonlResult<'a> {
+    let mut result: JsonlResult = JsonlResult::default();
+    for (index, raw) in text.lines().enumerate() {
+        if raw.trim().is_empty() {
+            continue;
+        }
+        result.lines_scanned += 1;
+        let value: Value = match serde_json::from_str(raw) {
+            Ok(value) => value,
+            Err(e) => {
+                result.invalid.push((index + 1, e.to_string()));
+                continue;
+            }
+        };
+        let matches: usize = if options.fields.is_empty() {
+            matcher.find_all(raw).len()
+        } else {
+            options
+                .fields
+                .iter()
+                .flat_map(|path| lookup(&value, path))
+                .map(|field| matcher.find_all(&value_text(field)).len())
+                .sum()
+        };
+        if matches == 0 {
+            continue;
+        }
+        let output: String = if options.print_fields.is_empty() {
+            raw.to_string()
+        } else {
+            // Written by hand rather than through a `Map`, which would sort the keys: the fields
+            // come out in the order they were asked for.
+            let mut selected: Vec<String> = Vec::new();
+            for (i, path) in options.print_fields.iter().enumerate() {
+                if options.print_fields[..i].contains(path) {
+                    continue;
+                }
+                let mut found: Vec<&Value> = lookup(&value, path);
+                let field: Value = match found.len() {
+                    0 => Value::Null,
+                    1 => found.remove(0).clone(),
+                    _ => Value::Array(found.into_iter().cloned().collect()),
+                };
+                selected.push(format!("{}:{field}", Value::from(path.as_str())));
+            }
+            format!("{{{}}}", selected.join(","))
+        };
+        result.matches.push(JsonlMatch { line_number: index + 1, raw, matches, output });
+    }
+    return result;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matcher::{CaseInsensitiveMatcher, LiteralMatcher};
+
+    const LOG: &str = r#"{"level":"error","msg":"db timeout","ctx":{"tags":["db","retry"]}}
+{"level":"info","msg":"error budget ok","code":200}
+not json
+{"level":"ERROR","msg":"disk full","ctx":{"tags":[]}}
+"#;
+
+    #[test]
+    fn lookup_paths() {
+        let value: Value = serde_json::from_str(LOG.lines().next().unwrap()).unwrap();
+        assert_eq!(vec![&Value::from("retry")], lookup(&value, "ctx.tags.1"));
+        assert_eq!(2, lookup(&value, "ctx.tags.*").len());
+        assert!(lookup(&value, "ctx.missing.x").is_empty());
+    }
+
+    #[test]
+    fn matches_only_the_chosen_field() {
+        let options: JsonlOptions = JsonlOptions { fields: vec![String::from("level")], ..JsonlOptions::default() };
+        let result: JsonlResult = search(&CaseInsensitiveMatcher::new("error"), LOG, &options);
+        let lines: Vec<usize> = result.matches.iter().map(|m| m.line_number).collect();
+        assert_eq!(vec![1, 4], lines);
+        assert_eq!(3, result.invalid[0].0);
+    }
+
+    #[test]
+    fn prints_selected_fields() {
+        let options: JsonlOptions = JsonlOptions {
+            fields: vec![String::from("code")],
+            print_fields: vec![String::from("msg"), String::from("missing")],
+        };
+        let result: JsonlResult = search(&LiteralMatcher::new("200"), LOG, &options);
+        assert_eq!(r#"{"msg":"error budget ok","missing":null}"#, result.matches[0].output);
+        let options: JsonlOptions = JsonlOptions { print_fields: vec![String::from("level"), String::from("code")], ..options };
+        let result: JsonlResult = search(&LiteralMatcher::new("200"), LOG, &options);
+        assert_eq!(r#"{"level":"info","code":200}"#, result.matches[0].output);
+    }
+}
@@ -1,9 +1,162 @@
+#![allow(clippy::needless_return)]
+
+use std::env;
 use std::error::Error;
-use std::fs;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use regex::Regex;
+
+pub mod archive;
+pub mod config_file;
+pub mod delimited;
+pub mod ffi;
+pub mod follow;
+pub mod glob;
+pub mod index;
+pub mod interactive;
+pub mod jsonl;
+pub mod matcher;
+mod modes;
+pub mod near;
+pub mod normalize;
+pub mod preprocess;
+pub mod query;
+pub mod rank;
+pub mod records;
+pub mod searcher;
+pub mod serve;
+pub mod stats;
+pub mod suggest;
+pub mod template;
+#[cfg(test)]
+pub(crate) mod test_util;
+pub mod timestamp;
+pub mod walk;
+pub mod watch;
+
+use archive::LineChunks;
+use config_file::ConfigFile;
+use delimited::{DelimitedOptions, DelimitedResult};
+use glob::{FileFilter, FileTypes};
+use index::{Index, UpdateSummary};
+use jsonl::{JsonlOptions, JsonlResult};
+use matcher::{CaseInsensitiveMatcher, Groups, LiteralMatcher, Match, MatchOptions};
+use near::{Near, Span, Window};
+use normalize::Normalization;
+use preprocess::Preprocessor;
+use query::Query;
+use records::Terminator;
+use searcher::{LineMatch, Searcher, SearcherBuilder};
+use stats::Stats;
+use suggest::Vocabulary;
+use template::{Fields, Template};
+use timestamp::{TimeFormat, TimeWindow};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Command {
+    #[default]
+    Search,
+    /// Build or update the on-disk index for a directory.
+    Index,
+    /// Print the configuration after merging the config file and the command line.
+    PrintConfig,
+    /// List the files a search would look at, without searching them.
+    Files,
+    /// Load a file once and read queries for it from a prompt.
+    Interactive,
+    /// Answer search requests for a directory from other programs over a socket.
+    Serve,
+}
+
+const DEFAULT_POLL_INTERVAL: u64 = 250;
+const DEFAULT_TOP: usize = 10;
 
+#[derive(Debug, Clone, Default)]
 pub struct Config {
+    pub command: Command,
     pub query: String,
     pub file_path: String,
+    pub ignore_case: bool,
+    /// Ignore case only when the query has no uppercase letters. The last of -i, -s and -S on
+    /// the command line wins; IGNORE_CASE only sets the default.
+    pub smart_case: bool,
+    /// Match canonically equivalent Unicode text (NFC and NFD), optionally ignoring accents.
+    pub normalization: Normalization,
+    pub use_index: bool,
+    /// Parse the query as a boolean expression (AND/OR/NOT, phrases, parentheses).
+    pub boolean: bool,
+    pub regex: bool,
+    pub multiline: bool,
+    pub line_number: bool,
+    /// What separates the records that are searched one at a time.
+    pub terminator: Terminator,
+    /// Globs choosing which files a directory search looks at; `!GLOB` excludes.
+    pub globs: Vec<String>,
+    /// Like `globs`, but matched ignoring case.
+    pub iglobs: Vec<String>,
+    /// File types to search (`-t`) and to skip (`-T`).
+    pub types: Vec<String>,
+    pub types_not: Vec<String>,
+    /// Extra file type definitions, like `web:*.html,*.css`.
+    pub type_adds: Vec<String>,
+    /// Parse files as delimited rows (CSV with ',', TSV with a tab) and search their fields.
+    pub delimiter: Option<char>,
+    /// Whether delimited files start with a header row; guessed when not set.
+    pub header: Option<bool>,
+    /// Delimited columns, by name or number, to match the query against.
+    pub columns: Vec<String>,
+    /// Delimited columns to print for matching rows.
+    pub select: Vec<String>,
+    /// Treat files as JSON Lines, matching the query against `fields` if any are given.
+    pub jsonl: bool,
+    pub fields: Vec<String>,
+    /// JSON fields to print for matching lines.
+    pub print_fields: Vec<String>,
+    /// Don't report lines that aren't valid JSON, just skip them.
+    pub skip_invalid: bool,
+    /// Only search log entries inside this time window.
+    pub since: Option<String>,
+    pub until: Option<String>,
+    /// The strftime-like format of the timestamps that start log lines.
+    pub time_format: Option<String>,
+    /// The logs are sorted by time, so the window can be found by binary search.
+    pub sorted: bool,
+    /// Look inside gzip files and tar archives.
+    pub search_zip: bool,
+    /// A command that converts files to text; its output is searched instead of the file.
+    pub pre: Option<String>,
+    /// The files that go through the preprocessor; all of them if empty.
+    pub pre_globs: Vec<String>,
+    /// Keep watching the file and print matching lines as they're appended.
+    pub follow: bool,
+    /// Re-run the search whenever a searched file changes, redrawing the results.
+    pub watch: bool,
+    /// How often follow and watch modes check for changes, in milliseconds.
+    pub poll_interval: u64,
+    /// Report places where all of the query's words occur within this many words or lines of
+    /// each other, instead of matching lines.
+    pub near: Option<Window>,
+    /// Print each match (or matching line) through this template instead of the usual way.
+    pub format: Option<Template>,
+    /// Follow printed file names with a NUL instead of a colon or newline, for `xargs -0`.
+    pub null: bool,
+    /// Print a summary of how much work the search did at the end.
+    pub stats: bool,
+    /// Rank files (or paragraphs, with --paragraph) by relevance instead of listing matches.
+    pub rank: bool,
+    /// How many results rank mode prints.
+    pub top: usize,
+    /// When nothing matches, suggest similar words from the searched files.
+    pub suggest: bool,
+    /// Where `serve` listens: HOST:PORT on localhost, or unix:PATH.
+    pub listen: Option<String>,
+    /// The config file profile to apply on top of its defaults.
+    pub profile: Option<String>,
+    pub no_config: bool,
 }
 
 impl Config {
@@ -13,48 +166,820 @@ impl Config {
         }
         let query: String = args[1].clone();
         let file_path: String = args[2].clone();
-        return Config { query, file_path };
+        return Config { query, file_path, ..Config::default() };
     }
     pub fn build(args: &[String]) -> Result<Config, &'static str> {
-        if args.len() < 3 {
+        return Config::build_ignoring_case(args, env::var("IGNORE_CASE").is_ok());
+    }
+
+    // `build` with the default that IGNORE_CASE sets passed in, so tests don't touch the
+    // environment.
+    fn build_ignoring_case(args: &[String], ignore_case: bool) -> Result<Config, &'static str> {
+        let mut config: Config = Config {
+            ignore_case,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            top: DEFAULT_TOP,
+            ..Config::default()
+        };
+        let mut positional: Vec<String> = Vec::new();
+        let mut args = args.iter().skip(1).peekable();
+        match args.peek().map(|arg| arg.as_str()) {
+            Some("index") => config.command = Command::Index,
+            Some("serve") => config.command = Command::Serve,
+            _ => {}
+        }
+        if config.command != Command::Search {
+            args.next();
+        }
+        let mut options_done: bool = false;
+        while let Some(arg) = args.next() {
+            if options_done {
+                positional.push(arg.clone());
+                continue;
+            }
+            // Every option can be turned off again with --no-NAME, which is how the command
+            // line overrides a config file. An option that takes a value goes back to its
+            // default, and one that can be repeated forgets every value given so far.
+            let negated: Option<String> = match arg.strip_prefix("--no-") {
+                Some(rest) if rest != "config" => Some(format!("--{rest}")),
+                _ => None,
+            };
+            let (flag, on): (&str, bool) = match &negated {
+                Some(flag) => (flag.as_str(), false),
+                None => (arg.as_str(), true),
+            };
+            match flag {
+                "--" => options_done = true,
+                "-i" | "--ignore-case" => (config.ignore_case, config.smart_case) = (on, false),
+                "-s" | "--case-sensitive" => (config.ignore_case, config.smart_case) = (!on, false),
+                "-S" | "--smart-case" => config.smart_case = on,
+                "--normalize" => config.normalization = if on { Normalization::Canonical } else { Normalization::None },
+                "--fold-accents" => config.normalization = if on { Normalization::FoldAccents } else { Normalization::None },
+                "--index" => config.use_index = on,
+                "--boolean" => config.boolean = on,
+                "-E" | "--regex" => config.regex = on,
+                "-U" | "--multiline" => config.multiline = on,
+                "-n" | "--line-number" => config.line_number = on,
+                "--stats" => config.stats = on,
+                "--rank" => config.rank = on,
+                "--near" if on => {
+                    let n: &String = args.next().ok_or("--near needs a number of words!")?;
+                    config.near = Some(Window::Words(n.parse().map_err(|_| "--near must be a number!")?));
+                }
+                "--near-lines" if on => {
+                    let n: &String = args.next().ok_or("--near-lines needs a number of lines!")?;
+                    config.near = Some(Window::Lines(n.parse().map_err(|_| "--near-lines must be a number!")?));
+                }
+                "--near" | "--near-lines" => config.near = None,
+                "--suggest" => config.suggest = on,
+                "--format" if on => config.format = Some(Template::parse(args.next().ok_or("--format needs a template!")?)?),
+                "--format" => config.format = None,
+                "--top" if on => {
+                    let top: &String = args.next().ok_or("--top needs a number!")?;
+                    config.top = top.parse().map_err(|_| "--top must be a number!")?;
+                }
+                "--top" => config.top = DEFAULT_TOP,
+                "-Z" | "--search-zip" => config.search_zip = on,
+                "--pre" if on => config.pre = Some(args.next().ok_or("--pre needs a command!")?.clone()),
+                "--pre" => config.pre = None,
+                "--listen" if on => config.listen = Some(args.next().ok_or("--listen needs an address!")?.clone()),
+                "--listen" => config.listen = None,
+                "--pre-glob" if on => config.pre_globs.push(args.next().ok_or("--pre-glob needs a pattern!")?.clone()),
+                "--pre-glob" => config.pre_globs.clear(),
+                "-z" | "--null-data" => config.terminator = if on { Terminator::Separator(String::from("\0")) } else { Terminator::Line },
+                "--paragraph" => config.terminator = if on { Terminator::Paragraph } else { Terminator::Line },
+                "--record-separator" if on => {
+                    let separator: String = records::unescape(args.next().ok_or("--record-separator needs a value!")?);
+                    if separator.is_empty() {
+                        return Err("--record-separator can't be empty!");
+                    }
+                    config.terminator = Terminator::Separator(separator);
+                }
+                "--record-separator" => config.terminator = Terminator::Line,
+                "--profile" if on => config.profile = Some(args.next().ok_or("--profile needs a name!")?.clone()),
+                "--profile" => config.profile = None,
+                "--no-config" => config.no_config = true,
+                "--print-config" if on => config.command = Command::PrintConfig,
+                "--files" if on => config.command = Command::Files,
+                "--interactive" if on => config.command = Command::Interactive,
+                "--print-config" | "--files" | "--interactive" => {
+                    if [Command::PrintConfig, Command::Files, Command::Interactive].contains(&config.command) {
+                        config.command = Command::Search;
+                    }
+                }
+                "-0" | "--null" => config.null = on,
+                "--csv" => config.delimiter = on.then_some(','),
+                "--tsv" => config.delimiter = on.then_some('\t'),
+                "--delimiter" if on => {
+                    let delimiter: String = records::unescape(args.next().ok_or("--delimiter needs a character!")?);
+                    let mut chars = delimiter.chars();
+                    config.delimiter = match (chars.next(), chars.next()) {
+                        (Some(c), None) => Some(c),
+                        _ => return Err("--delimiter must be a single character!"),
+                    };
+                }
+                "--delimiter" => config.delimiter = None,
+                "--header" => config.header = Some(on),
+                "--jsonl" => config.jsonl = on,
+                "--field" if on => config.fields.push(args.next().ok_or("--field needs a field path!")?.clone()),
+                "--field" => config.fields.clear(),
+                "--print-fields" if on => config
+                    .print_fields
+                    .extend(args.next().ok_or("--print-fields needs field paths!")?.split(',').map(String::from)),
+                "--print-fields" => config.print_fields.clear(),
+                "--skip-invalid" => config.skip_invalid = on,
+                "--since" if on => config.since = Some(args.next().ok_or("--since needs a time!")?.clone()),
+                "--since" => config.since = None,
+                "--until" if on => config.until = Some(args.next().ok_or("--until needs a time!")?.clone()),
+                "--until" => config.until = None,
+                "--time-format" if on => config.time_format = Some(args.next().ok_or("--time-format needs a format!")?.clone()),
+                "--time-format" => config.time_format = None,
+                "--sorted" => config.sorted = on,
+                "-f" | "--follow" => config.follow = on,
+                "--watch" => config.watch = on,
+                "--poll-interval" if on => {
+                    let millis: &String = args.next().ok_or("--poll-interval needs milliseconds!")?;
+                    config.poll_interval = millis.parse().map_err(|_| "--poll-interval must be a number of milliseconds!")?;
+                }
+                "--poll-interval" => config.poll_interval = DEFAULT_POLL_INTERVAL,
+                "--columns" if on => config.columns.push(args.next().ok_or("--columns needs column names or numbers!")?.clone()),
+                "--columns" => config.columns.clear(),
+                "--select" if on => config.select.push(args.next().ok_or("--select needs column names or numbers!")?.clone()),
+                "--select" => config.select.clear(),
+                "-g" | "--glob" if on => config.globs.push(args.next().ok_or("--glob needs a pattern!")?.clone()),
+                "--glob" => config.globs.clear(),
+                "--iglob" if on => config.iglobs.push(args.next().ok_or("--iglob needs a pattern!")?.clone()),
+                "--iglob" => config.iglobs.clear(),
+                "-t" | "--type" if on => config.types.push(args.next().ok_or("--type needs a type name!")?.clone()),
+                "--type" => config.types.clear(),
+                "-T" | "--type-not" if on => config.types_not.push(args.next().ok_or("--type-not needs a type name!")?.clone()),
+                "--type-not" => config.types_not.clear(),
+                "--type-add" if on => config.type_adds.push(args.next().ok_or("--type-add needs NAME:GLOB!")?.clone()),
+                "--type-add" => config.type_adds.clear(),
+                flag if flag.starts_with('-') && flag.len() > 1 => return Err("Unknown option! Put -- before a query that starts with -."),
+                _ => positional.push(arg.clone()),
+            }
+        }
+        if config.command == Command::PrintConfig {
+            let mut positional = positional.into_iter();
+            config.query = positional.next().unwrap_or_default();
+            config.file_path = positional.next().unwrap_or_default();
+            return Ok(config);
+        }
+        if config.command == Command::Index || config.command == Command::Files || config.command == Command::Serve {
+            config.file_path = match positional.len() {
+                0 => String::from("."),
+                1 => positional[0].clone(),
+                _ => return Err("Too many arguments!"),
+            };
+            return Ok(config);
+        }
+        if config.command == Command::Interactive {
+            // The query is optional here; if given it's run before the first prompt.
+            match positional.len() {
+                0 => return Err("Not enough arguments!"),
+                1 => config.file_path = positional[0].clone(),
+                2 => (config.query, config.file_path) = (positional[0].clone(), positional[1].clone()),
+                _ => return Err("Too many arguments!"),
+            }
+            return Ok(config);
+        }
+        if positional.len() < 2 {
             return Err("Not enough arguments!");
         }
-        let query: String = args[1].clone();
-        let file_path: String = args[2].clone();
-        return Ok(Config {query, file_path });
+        config.query = positional[0].clone();
+        config.file_path = positional[1].clone();
+        if config.follow {
+            // Following matches each appended line on its own as it arrives, so options that
+            // need a whole record, several lines or the whole file can't be used with it.
+            let conflicts: [(bool, &'static str); 7] = [
+                (config.jsonl, "--follow can't be used with --jsonl!"),
+                (config.delimiter.is_some(), "--follow can't be used with --csv, --tsv or --delimiter!"),
+                (config.since.is_some() || config.until.is_some(), "--follow can't be used with --since or --until!"),
+                (config.near.is_some(), "--follow can't be used with --near or --near-lines!"),
+                (config.multiline, "--follow can't be used with --multiline!"),
+                (config.terminator != Terminator::Line, "--follow can't be used with --null-data, --paragraph or --record-separator!"),
+                (config.pre.is_some(), "--follow can't be used with --pre!"),
+            ];
+            if let Some((_, message)) = conflicts.iter().find(|(conflict, _)| *conflict) {
+                return Err(message);
+            }
+        }
+        return Ok(config);
+    }
+
+    /// Like `build`, but first applies the defaults (and the selected profile) from the config
+    /// file, unless `--no-config` is given. Arguments on the command line override the file.
+    pub fn load(args: &[String]) -> Result<Config, Box<dyn Error>> {
+        return Config::load_from(args, |name| env::var_os(name));
+    }
+
+    /// `load`, looking up where the config file is with `var` instead of the real environment.
+    fn load_from(args: &[String], var: impl Fn(&str) -> Option<OsString>) -> Result<Config, Box<dyn Error>> {
+        let cli: Config = Config::build(args)?;
+        if cli.no_config {
+            return Ok(cli);
+        }
+        let (path, file): (PathBuf, ConfigFile) = match ConfigFile::load_from(var)? {
+            Some(found) => found,
+            None => match &cli.profile {
+                Some(name) => return Err(format!("no config file to read profile `{name}` from").into()),
+                None => return Ok(cli),
+            },
+        };
+        let mut merged: Vec<String> = vec![args[0].clone()];
+        merged.extend(file.args(cli.profile.as_deref())?);
+        // The file's options go after `index` or `serve` so the subcommand stays first.
+        if cli.command == Command::Index || cli.command == Command::Serve {
+            merged.insert(1, args[1].clone());
+            merged.extend_from_slice(&args[2..]);
+        } else {
+            merged.extend_from_slice(&args[1..]);
+        }
+        return Config::build(&merged).map_err(|err| format!("{err} (in config file {})", path.display()).into());
+    }
+
+    /// The options for searching delimited files, if that mode is on.
+    pub fn delimited_options(&self) -> Option<DelimitedOptions> {
+        return Some(DelimitedOptions {
+            delimiter: self.delimiter?,
+            header: self.header,
+            columns: self.columns.clone(),
+            select: self.select.clone(),
+        });
+    }
+
+    /// The options for searching JSON Lines files, if that mode is on.
+    pub fn jsonl_options(&self) -> Option<JsonlOptions> {
+        if !self.jsonl {
+            return None;
+        }
+        return Some(JsonlOptions { fields: self.fields.clone(), print_fields: self.print_fields.clone() });
+    }
+
+    /// The proximity search to run instead of matching lines, if --near or --near-lines was
+    /// given. Each of its words ignores case the same way a plain pattern would.
+    pub fn near(&self) -> Result<Option<Near>, Box<dyn Error>> {
+        let window: Window = match self.near {
+            Some(window) => window,
+            None => return Ok(None),
+        };
+        if self.regex || self.boolean {
+            return Err("--near can't be combined with --regex or --boolean".into());
+        }
+        return Ok(Some(Near::new(&self.query, window, &self.match_options())?));
+    }
+
+    /// Checks that the capture groups --format mentions are in the pattern.
+    pub fn check_format(&self) -> Result<(), Box<dyn Error>> {
+        let template: &Template = match &self.format {
+            Some(template) => template,
+            None => return Ok(()),
+        };
+        // A boolean query is several patterns, and its groups can't be numbered as one regex.
+        if self.boolean && template.uses_groups() {
+            return Err("capture groups in --format can't be used with --boolean".into());
+        }
+        let regex: Option<Regex> = if self.regex { Some(Regex::new(&self.query)?) } else { None };
+        template.check_groups(regex.as_ref())?;
+        return Ok(());
+    }
+
+    /// The time window to restrict the search to, if --since or --until was given.
+    pub fn time_window(&self) -> Result<Option<TimeWindow>, Box<dyn Error>> {
+        if self.since.is_none() && self.until.is_none() {
+            return Ok(None);
+        }
+        let format: TimeFormat = match &self.time_format {
+            Some(format) => TimeFormat::Custom(format.clone()),
+            None => TimeFormat::Auto,
+        };
+        let year: i64 = timestamp::current_year();
+        let since = self.since.as_deref().map(|text| format.parse_bound(text, year)).transpose()?;
+        let until = self.until.as_deref().map(|text| format.parse_until(text, year)).transpose()?;
+        return Ok(Some(TimeWindow { since, until, format, year }));
+    }
+
+    /// Builds the filter that decides which files a directory search looks at.
+    pub fn file_filter(&self) -> Result<FileFilter, Box<dyn Error>> {
+        let mut types: FileTypes = FileTypes::default();
+        for definition in &self.type_adds {
+            types.add(definition)?;
+        }
+        let mut filter: FileFilter = FileFilter::default();
+        for glob in &self.globs {
+            filter.add_glob(glob, false)?;
+        }
+        for glob in &self.iglobs {
+            filter.add_glob(glob, true)?;
+        }
+        for name in &self.types {
+            filter.select_type(&types, name)?;
+        }
+        for name in &self.types_not {
+            filter.reject_type(&types, name)?;
+        }
+        return Ok(filter);
+    }
+
+    /// The preprocessor to run files through, if --pre was given.
+    pub fn preprocessor(&self) -> Result<Option<Preprocessor>, Box<dyn Error>> {
+        return self.pre.as_deref().map(|command| Preprocessor::new(command, &self.pre_globs)).transpose();
+    }
+
+    // The index holds the trigrams of each file's raw text. When the text that gets searched is
+    // normalized, decompressed, preprocessed or decoded from JSON or CSV fields first (where
+    // `\u00e9` or `""` escapes differ from the raw bytes), the index can't rule any file out.
+    fn index_sees_searched_text(&self) -> bool {
+        return self.normalization == Normalization::None
+            && !self.search_zip
+            && self.pre.is_none()
+            && !self.allows_nul()
+            && !self.jsonl
+            && self.delimiter.is_none();
+    }
+
+    // Whether lines have to be counted to get their real numbers, which a file read from the
+    // middle (a sorted time window, or --follow) otherwise skips.
+    fn needs_line_numbers(&self) -> bool {
+        return self.line_number || self.format.as_ref().is_some_and(|template| template.uses_line_number());
+    }
+
+    // NUL-separated records are text with NULs in it, so NULs don't make a file binary then.
+    // The index still treats such files as binary.
+    fn allows_nul(&self) -> bool {
+        return self.terminator == Terminator::Separator(String::from("\0"));
+    }
+
+    // What each result ends with: NUL-separated records stay NUL-terminated unless they're
+    // printed line by line or through a --format template.
+    fn result_end(&self) -> &'static str {
+        return if self.allows_nul() && self.format.is_none() && !self.multiline { "\0" } else { "\n" };
+    }
+
+    /// How the searcher turns each pattern, or each term of a boolean query, into a matcher.
+    pub fn match_options(&self) -> MatchOptions {
+        return MatchOptions {
+            ignore_case: self.ignore_case,
+            smart_case: self.smart_case,
+            regex: self.regex,
+            normalization: self.normalization,
+        };
+    }
+
+    /// Builds the Searcher described by this configuration.
+    pub fn searcher(&self) -> Result<Searcher, Box<dyn Error>> {
+        return SearcherBuilder::new()
+            .pattern(&self.query)
+            .ignore_case(self.ignore_case)
+            .smart_case(self.smart_case)
+            .normalization(self.normalization)
+            .boolean(self.boolean)
+            .regex(self.regex)
+            .multiline(self.multiline)
+            .terminator(self.terminator.clone())
+            .build();
     }
 }
+/// Searches the files `config` names and prints the results. The other commands and the
+/// long-running modes (--follow, --watch, --rank) are run by the modes module.
 pub fn run (config: Config) -> Result<(), Box<dyn Error>> {
-    let contents: String = fs::read_to_string(config.file_path)?;
-    //println!("With text:\n{contents}");
-    for line in search(&config.query, &contents) {
-        println!("{line}");
+    if modes::applies(&config) {
+        return modes::run(config);
+    }
+    config.check_format()?;
+    let start: Instant = Instant::now();
+    let searcher: Searcher = config.searcher()?;
+    let files: Vec<PathBuf> = files_to_search(&config)?;
+    let mut stats: Stats = search_files(&config, &searcher, &files, &mut |output: &str| print!("{output}"))?;
+    if config.suggest && stats.matched_lines == 0 {
+        for message in suggestions(&config)? {
+            eprintln!("{message}");
+        }
+    }
+    if config.stats {
+        stats.elapsed = start.elapsed();
+        println!();
+        println!("{stats}");
+    }
+    if !stats.errors.is_empty() {
+        return Err(stats.errors.join("\n").into());
     }
     return Ok(());
 }
-pub fn search<'a>(query: &str, contents: &'a str)->Vec<&'a str> {
-    let mut results: Vec<&str> = Vec::new();
-    for line in contents.lines() {
-        if line.contains(query) {
-            results.push(line);
+
+// "Did you mean" messages for the words of a query that found nothing. The vocabulary comes
+// from every file the search could look at, not just the index's candidates, and only words
+// that don't occur in it get a message. Regexes aren't words, so they get no suggestions.
+fn suggestions(config: &Config) -> Result<Vec<String>, Box<dyn Error>> {
+    if config.regex {
+        return Ok(Vec::new());
+    }
+    let root: &Path = Path::new(&config.file_path);
+    let files: Vec<PathBuf> = if root.is_dir() { walk::walk_filtered(root, &config.file_filter()?)? } else { vec![root.to_path_buf()] };
+    let mut vocabulary: Vocabulary = Vocabulary::default();
+    for path in &files {
+        if let Ok(contents) = walk::read_text(path, false) {
+            vocabulary.add_text(&contents);
+        }
+    }
+    let terms: Vec<String> = if config.boolean {
+        Query::parse(&config.query)?.terms().into_iter().map(String::from).collect()
+    } else {
+        vec![config.query.clone()]
+    };
+    let options: MatchOptions = config.match_options();
+    let mut messages: Vec<String> = Vec::new();
+    for term in &terms {
+        let ignore_case: bool = options.ignores_case(term);
+        for word in term.split(|c: char| !(c.is_alphanumeric() || c == '_')).filter(|word| !word.is_empty()) {
+            if !vocabulary.contains(word, ignore_case) {
+                messages.push(suggest::message(word, &vocabulary.suggest(word)));
+            }
         }
     }
-    return results;
+    return Ok(messages);
 }
-pub fn search_case_insensitive<'a>(query: &str, contents: &'a str)->Vec<&'a str> {
-    let query: String = query.to_lowercase();
-    let mut results: Vec<&str> = Vec::new();
-    for line in contents.lines() {
-        if line.to_lowercase().contains(&query){
-            results.push(line);
+
+// The files a search looks at: the named file, or the files under the named directory that
+// the filters allow, narrowed down by the index when it's in use.
+fn files_to_search(config: &Config) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let root: &Path = Path::new(&config.file_path);
+    let filter: FileFilter = config.file_filter()?;
+    let mut files: Vec<PathBuf> = if !root.is_dir() {
+        vec![root.to_path_buf()]
+    } else if config.use_index {
+        // Refresh the index first so files edited since the last `index` run aren't missed.
+        let mut index: Index = Index::open(root)?;
+        let summary: UpdateSummary = index.update(root)?;
+        if summary.reindexed > 0 || summary.removed > 0 || !Index::index_path(root).exists() {
+            index.save(root)?;
+        }
+        if config.regex || !config.index_sees_searched_text() {
+            // Trigrams can't be pulled out of a regex, so every file is a candidate.
+            walk::walk_filtered(root, &filter)?
+        } else if config.boolean {
+            let query: Query = Query::parse(&config.query)?;
+            index.candidates_by(root, |may_contain| query.may_match(may_contain))
+        } else if let Some(near) = config.near()? {
+            index.candidates_by(root, |may_contain| near.terms().iter().all(|term| may_contain(term)))
+        } else {
+            index.candidates(root, &config.query)
+        }
+    } else {
+        walk::walk_filtered(root, &filter)?
+    };
+    if config.use_index && root.is_dir() {
+        // The index covers every file, so apply the globs and types to its candidates.
+        files.retain(|path| filter.allows_file(path.strip_prefix(root).unwrap_or(path)));
+    }
+    return Ok(files);
+}
+
+// Searches `files`, handing everything that would be printed to `emit`.
+fn search_files(config: &Config, searcher: &Searcher, files: &[PathBuf], emit: &mut dyn FnMut(&str)) -> Result<Stats, Box<dyn Error>> {
+    let show_path: bool = Path::new(&config.file_path).is_dir();
+    let mut stats: Stats = Stats::default();
+    let window: Option<TimeWindow> = config.time_window()?;
+    let preprocessor: Option<Preprocessor> = config.preprocessor()?;
+    let root: &Path = Path::new(&config.file_path);
+    for path in files {
+        let relative: &Path = if show_path {
+            path.strip_prefix(root).unwrap_or(path)
+        } else {
+            Path::new(path.file_name().unwrap_or_default())
+        };
+        if let Some(preprocessor) = &preprocessor
+            && preprocessor.applies_to(relative)
+        {
+            // A failing preprocessor is recorded for its file without stopping the search.
+            match preprocessor.run(path) {
+                Ok(text) => stats += search_text(config, searcher, window.as_ref(), show_path.then_some(path.as_path()), &text, 1, emit)?,
+                Err(e) => stats.errors.push(e.to_string()),
+            }
+            continue;
+        }
+        if config.search_zip
+            && let Some(kind) = archive::kind(path)
+        {
+            stats += search_archive(config, searcher, window.as_ref(), path, kind, emit)?;
+            continue;
         }
+        let (contents, first_line): (String, usize) = match read_contents(config, window.as_ref(), path) {
+            Ok(read) => read,
+            // Skip binary files rather than failing the whole directory search.
+            Err(e) if show_path && e.kind() == io::ErrorKind::InvalidData => continue,
+            Err(e) => return Err(e.into()),
+        };
+        //println!("With text:\n{contents}");
+        let name: Option<&Path> = show_path.then_some(path.as_path());
+        stats += search_text(config, searcher, window.as_ref(), name, &contents, first_line, emit)?;
+    }
+    return Ok(stats);
+}
+
+// Searches a whole file's contents, restricted to the time window if there is one.
+// `first_line` is the line number the contents start at.
+fn search_text(
+    config: &Config,
+    searcher: &Searcher,
+    window: Option<&TimeWindow>,
+    name: Option<&Path>,
+    contents: &str,
+    first_line: usize,
+    emit: &mut dyn FnMut(&str),
+) -> Result<Stats, Box<dyn Error>> {
+    let window: &TimeWindow = match window {
+        Some(window) => window,
+        None => return search_contents(config, searcher, name, contents, first_line - 1, emit),
+    };
+    let mut file_stats: Stats = Stats::default();
+    for segment in window.segments(contents, config.sorted) {
+        file_stats += search_contents(config, searcher, name, segment.text, first_line + segment.first_line - 2, emit)?;
     }
-    return results;
+    file_stats.files_searched = 1;
+    file_stats.files_matched = (file_stats.matched_lines > 0) as usize;
+    file_stats.bytes_read = contents.len() as u64;
+    return Ok(file_stats);
 }
 
+// Searches each file inside a gzip file or tar archive. Binary members are skipped, and an
+// archive that can't be read to the end (corrupt or truncated) is recorded in the stats'
+// errors without stopping the search of the other files.
+fn search_archive(
+    config: &Config,
+    searcher: &Searcher,
+    window: Option<&TimeWindow>,
+    path: &Path,
+    kind: archive::Kind,
+    emit: &mut dyn FnMut(&str),
+) -> Result<Stats, Box<dyn Error>> {
+    let show_path: bool = Path::new(&config.file_path).is_dir();
+    let mut stats: Stats = Stats::default();
+    let read: io::Result<()> = archive::for_each_member(path, kind, |member, reader| {
+        let name: Option<PathBuf> = match member {
+            Some(member) => Some(PathBuf::from(format!("{}:{member}", path.display()))),
+            None => show_path.then(|| path.to_path_buf()),
+        };
+        match search_member(config, searcher, window, name.as_deref(), reader, emit) {
+            Ok(member_stats) => stats += member_stats,
+            Err(e) if e.downcast_ref::<io::Error>().is_some_and(|e| e.kind() == io::ErrorKind::InvalidData) => {}
+            Err(e) => return Err(io::Error::other(e.to_string())),
+        }
+        return Ok(());
+    });
+    if let Err(e) = read {
+        stats.errors.push(format!("{}: {e}", path.display()));
+    }
+    return Ok(stats);
+}
+
+// Searches one archive member as it is decompressed. A line-by-line search reads it in chunks
+// of whole lines; the modes that need all of it at once (multiline, other record separators,
+// CSV, time windows) read the member, but never the rest of the archive.
+fn search_member(
+    config: &Config,
+    searcher: &Searcher,
+    window: Option<&TimeWindow>,
+    name: Option<&Path>,
+    reader: &mut dyn Read,
+    emit: &mut dyn FnMut(&str),
+) -> Result<Stats, Box<dyn Error>> {
+    let streaming: bool =
+        !config.multiline && config.terminator == Terminator::Line && config.delimiter.is_none() && window.is_none();
+    if !streaming {
+        let mut contents: String = String::new();
+        reader.read_to_string(&mut contents)?;
+        if !config.allows_nul() && walk::looks_binary(contents.as_bytes()) {
+            return Err(walk::binary_error().into());
+        }
+        return search_text(config, searcher, window, name, &contents, 1, emit);
+    }
+    let mut chunks: LineChunks<&mut dyn Read> = LineChunks::new(reader, archive::CHUNK_SIZE);
+    let mut chunk: String = String::new();
+    let mut member_stats: Stats = Stats::default();
+    let mut lines_before: usize = 0;
+    while chunks.next_chunk(&mut chunk)? {
+        if lines_before == 0 && walk::looks_binary(chunk.as_bytes()) {
+            return Err(walk::binary_error().into());
+        }
+        member_stats += search_contents(config, searcher, name, &chunk, lines_before, emit)?;
+        lines_before += chunk.lines().count();
+    }
+    member_stats.files_searched = 1;
+    member_stats.files_matched = (member_stats.matched_lines > 0) as usize;
+    return Ok(member_stats);
+}
+
+// Reads a file and returns its contents with the line number they start at. For a sorted log
+// with a time window, reading starts at the window instead of the beginning of the file.
+fn read_contents(config: &Config, window: Option<&TimeWindow>, path: &Path) -> io::Result<(String, usize)> {
+    let window: &TimeWindow = match window {
+        Some(window) if config.sorted => window,
+        _ => return Ok((walk::read_text(path, config.allows_nul())?, 1)),
+    };
+    let mut file: File = File::open(path)?;
+    let len: u64 = file.metadata()?.len();
+    let start: u64 = window.seek_start(&mut file, len)?;
+    // Line numbers mean counting the lines that were skipped, but not searching them.
+    let mut first_line: usize = 1;
+    if config.needs_line_numbers() {
+        file.seek(SeekFrom::Start(0))?;
+        let mut skipped = BufReader::new(Read::by_ref(&mut file).take(start));
+        loop {
+            let buffer: &[u8] = skipped.fill_buf()?;
+            if buffer.is_empty() {
+                break;
+            }
+            first_line += buffer.iter().filter(|&&b| b == b'\n').count();
+            let consumed: usize = buffer.len();
+            skipped.consume(consumed);
+        }
+    }
+    file.seek(SeekFrom::Start(start))?;
+    let mut contents: String = String::new();
+    file.read_to_string(&mut contents)?;
+    if !config.allows_nul() && walk::looks_binary(contents.as_bytes()) {
+        return Err(walk::binary_error());
+    }
+    return Ok((contents, first_line));
+}
+
+// Searches one file's contents in whatever mode the config asks for, handing the formatted
+// results to `emit`. `line_offset` is added to line numbers, for contents that don't start at
+// line 1.
+fn search_contents(
+    config: &Config,
+    searcher: &Searcher,
+    path: Option<&Path>,
+    contents: &str,
+    line_offset: usize,
+    emit: &mut dyn FnMut(&str),
+) -> Result<Stats, Box<dyn Error>> {
+    if let Some(options) = config.delimited_options() {
+        let start: Instant = Instant::now();
+        let result: DelimitedResult = delimited::search(searcher.matcher(), contents, &options)
+            .map_err(|e| format!("{}: {e}", path.map(|p| p.display().to_string()).unwrap_or(config.file_path.clone())))?;
+        let mut stats: Stats = Stats {
+            files_searched: 1,
+            files_matched: (!result.matches.is_empty()) as usize,
+            bytes_read: contents.len() as u64,
+            lines_scanned: result.rows_scanned as u64,
+            matched_lines: result.matches.len() as u64,
+            ..Stats::default()
+        };
+        for row_match in &result.matches {
+            stats.matches += row_match.field_matches.iter().map(|(_, found)| found.len() as u64).sum::<u64>();
+            let line_match: LineMatch = LineMatch { line_number: row_match.row.line_number, line: &row_match.output, matches: Vec::new() };
+            emit(&format_match(config, searcher, path, &line_match, line_offset));
+        }
+        stats.elapsed = start.elapsed();
+        return Ok(stats);
+    }
+    if let Some(near) = config.near()? {
+        let start: Instant = Instant::now();
+        let spans: Vec<Span> = near.find(contents);
+        let mut stats: Stats = Stats {
+            files_searched: 1,
+            files_matched: (!spans.is_empty()) as usize,
+            bytes_read: contents.len() as u64,
+            lines_scanned: contents.lines().count() as u64,
+            matched_lines: spans.len() as u64,
+            matches: spans.len() as u64,
+            ..Stats::default()
+        };
+        for (i, span) in spans.iter().enumerate() {
+            if i > 0 && matches!(config.near, Some(Window::Lines(_))) {
+                emit("--\n");
+            }
+            emit(&format_span(config, path, contents, span, line_offset));
+        }
+        stats.elapsed = start.elapsed();
+        return Ok(stats);
+    }
+    if let Some(options) = config.jsonl_options() {
+        let start: Instant = Instant::now();
+        let result: JsonlResult = jsonl::search(searcher.matcher(), contents, &options);
+        if !config.skip_invalid {
+            let name: String = path.map(|p| p.display().to_string()).unwrap_or(config.file_path.clone());
+            for (line_number, error) in &result.invalid {
+                eprintln!("{name}:{line_number}: invalid JSON: {error}");
+            }
+        }
+        let mut stats: Stats = Stats {
+            files_searched: 1,
+            files_matched: (!result.matches.is_empty()) as usize,
+            bytes_read: contents.len() as u64,
+            lines_scanned: result.lines_scanned as u64,
+            matched_lines: result.matches.len() as u64,
+            ..Stats::default()
+        };
+        for json_match in &result.matches {
+            stats.matches += json_match.matches as u64;
+            let line_match: LineMatch = LineMatch { line_number: json_match.line_number, line: &json_match.output, matches: Vec::new() };
+            emit(&format_match(config, searcher, path, &line_match, line_offset));
+        }
+        stats.elapsed = start.elapsed();
+        return Ok(stats);
+    }
+    return Ok(searcher.search_with(contents, |line_match| {
+        emit(&format_match(config, searcher, path, line_match, line_offset));
+        return true;
+    }));
+}
+
+// Formats a proximity match. A word span is printed on one line, with the range of lines it
+// came from; a line span is printed line by line, like a multiline match.
+fn format_span(config: &Config, path: Option<&Path>, contents: &str, span: &Span, line_offset: usize) -> String {
+    let mut prefix: String = String::new();
+    if let Some(path) = path {
+        prefix.push_str(&format!("{}{}", path.display(), if config.null { '\0' } else { ':' }));
+    }
+    let text: &str = &contents[span.start..span.end];
+    let (first, last) = (span.first_line + line_offset, span.last_line + line_offset);
+    if let Some(template) = &config.format {
+        let name: String = path.map_or(config.file_path.clone(), |p| p.display().to_string());
+        return template.render(&Fields { path: &name, line_number: first, line: text, matched: Match::new(0, text.len()), groups: None });
+    }
+    if let Some(Window::Lines(_)) = config.near {
+        let mut output: String = String::new();
+        for (offset, line) in text.split('\n').enumerate() {
+            let line: &str = line.strip_suffix('\r').unwrap_or(line);
+            if config.line_number {
+                output.push_str(&format!("{prefix}{}:{line}\n", first + offset));
+            } else {
+                output.push_str(&format!("{prefix}{line}\n"));
+            }
+        }
+        return output;
+    }
+    if config.line_number {
+        prefix.push_str(&if first == last { format!("{first}:") } else { format!("{first}-{last}:") });
+    }
+    return format!("{prefix}{}\n", text.split_whitespace().collect::<Vec<&str>>().join(" "));
+}
+
+// Formats a matching line, or every line of a multiline match or paragraph, with the prefixes
+// asked for. Records with a custom separator are printed whole; NUL records stay NUL-terminated.
+fn format_match(config: &Config, searcher: &Searcher, path: Option<&Path>, line_match: &LineMatch, line_offset: usize) -> String {
+    if let Some(template) = &config.format {
+        let name: String = path.map_or(config.file_path.clone(), |p| p.display().to_string());
+        let line_number: usize = line_match.line_number + line_offset;
+        let line: &str = line_match.line;
+        if !template.per_match() || line_match.matches.is_empty() {
+            return template.render(&Fields { path: &name, line_number, line, matched: Match::new(0, line.len()), groups: None });
+        }
+        let mut output: String = String::new();
+        for &matched in &line_match.matches {
+            let groups: Option<Groups> = searcher.matcher().groups(line, matched);
+            output.push_str(&template.render(&Fields { path: &name, line_number, line, matched, groups: groups.as_ref() }));
+        }
+        return output;
+    }
+    let mut prefix: String = String::new();
+    if let Some(path) = path {
+        prefix.push_str(&format!("{}{}", path.display(), if config.null { '\0' } else { ':' }));
+    }
+    if config.multiline || config.terminator.numbers_lines() {
+        let mut output: String = String::new();
+        for (offset, line) in line_match.line.split('\n').enumerate() {
+            let line: &str = line.strip_suffix('\r').unwrap_or(line);
+            if config.line_number {
+                output.push_str(&format!("{prefix}{}:{line}\n", line_match.line_number + line_offset + offset));
+            } else {
+                output.push_str(&format!("{prefix}{line}\n"));
+            }
+        }
+        if config.terminator == Terminator::Paragraph && !config.multiline {
+            output.push('\n');
+        }
+        return output;
+    }
+    if config.line_number {
+        prefix.push_str(&format!("{}:", line_match.line_number + line_offset));
+    }
+    return format!("{prefix}{}{}", line_match.line, config.result_end());
+}
+
+pub fn search<'a>(query: &str, contents: &'a str)->Vec<&'a str> {
+    let searcher: Searcher = Searcher::new(Box::new(LiteralMatcher::new(query)));
+    return searcher.search(contents).map(|m| m.line).collect();
+}
+pub fn search_case_insensitive<'a>(query: &str, contents: &'a str)->Vec<&'a str> {
+    let searcher: Searcher = Searcher::new(Box::new(CaseInsensitiveMatcher::new(query)));
+    return searcher.search(contents).map(|m| m.line).collect();
+}
+/// Ignores case if `query` is all lowercase, and matches it exactly otherwise.
+pub fn search_smart_case<'a>(query: &str, contents: &'a str)->Vec<&'a str> {
+    if matcher::has_uppercase(query, false) {
+        return search(query, contents);
+    }
+    return search_case_insensitive(query, contents);
+}
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::fs;
 
     #[test]
     fn one_result() {
@@ -91,4 +1016,252 @@ Trust me.";
             search_case_insensitive(query, contents)
         );
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn smart_case() {
+        let contents = "\
+Rust:
+Trust me.";
+        assert_eq!(vec!["Rust:", "Trust me."], search_smart_case("rust", contents));
+        assert_eq!(vec!["Rust:"], search_smart_case("Rust", contents));
+        let args: Vec<String> = ["minigrep", "-S", "-i", "to", "poem.txt"].iter().map(|s| s.to_string()).collect();
+        let config: Config = Config::build(&args).unwrap();
+        assert!(config.ignore_case && !config.smart_case);
+    }
+
+    #[test]
+    fn last_case_option_wins_over_ignore_case_default() {
+        // (IGNORE_CASE set, options) -> whether "rust" and "Rust" ignore case.
+        let cases: [(bool, &[&str], bool, bool); 6] = [
+            (true, &[], true, true),
+            (true, &["-s"], false, false),
+            (true, &["-S"], true, false),
+            (false, &["-S", "-i"], true, true),
+            (false, &["-i", "-S"], true, false),
+            (true, &["-S", "-s"], false, false),
+        ];
+        for (ignore_case, options, lowercase, uppercase) in cases {
+            let args: Vec<String> =
+                ["minigrep"].iter().chain(options).chain(&["to", "poem.txt"]).map(|s| s.to_string()).collect();
+            let config: Config = Config::build_ignoring_case(&args, ignore_case).unwrap();
+            let decided: (bool, bool) = (config.match_options().ignores_case("rust"), config.match_options().ignores_case("Rust"));
+            assert_eq!((lowercase, uppercase), decided, "IGNORE_CASE={ignore_case} {options:?}");
+        }
+        // Smart case looks at each term of a boolean query, not at the operators.
+        let args: Vec<String> =
+            ["minigrep", "-S", "--boolean", "error AND timeout", "poem.txt"].iter().map(|s| s.to_string()).collect();
+        let config: Config = Config::build(&args).unwrap();
+        let terms: Vec<String> = Query::parse(&config.query).unwrap().terms().into_iter().map(String::from).collect();
+        assert!(terms.iter().all(|term| config.match_options().ignores_case(term)));
+    }
+
+    #[test]
+    fn later_arguments_override_earlier_ones() {
+        let args: Vec<String> = ["minigrep", "--line-number", "--profile", "logs", "--no-line-number", "to", "poem.txt"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let config: Config = Config::build(&args).unwrap();
+        assert!(!config.line_number);
+        assert_eq!(Some("logs"), config.profile.as_deref());
+    }
+
+    #[test]
+    fn negating_value_options_resets_them() {
+        let args: Vec<String> = [
+            "minigrep", "--since", "2024-01-01", "--top", "3", "--glob", "*.log", "--glob", "*.txt", "--poll-interval", "5",
+            "--no-since", "--no-top", "--no-glob", "--no-poll-interval", "--glob", "*.md", "to", "poem.txt",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        let config: Config = Config::build(&args).unwrap();
+        assert_eq!(None, config.since);
+        assert_eq!(DEFAULT_TOP, config.top);
+        assert_eq!(DEFAULT_POLL_INTERVAL, config.poll_interval);
+        assert_eq!(vec![String::from("*.md")], config.globs);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn failing_preprocessor_fails_the_search() {
+        let dir: PathBuf = test_util::scratch_dir("failing-pre", &[("a.pdf", "to do"), ("b.txt", "to be")]);
+        let args: Vec<String> = ["minigrep", "--pre", "false", "--pre-glob", "*.pdf", "to"]
+            .iter()
+            .map(|s| s.to_string())
+            .chain([dir.display().to_string()])
+            .collect();
+        let config: Config = Config::build(&args).unwrap();
+        let searcher: Searcher = config.searcher().unwrap();
+        let files: Vec<PathBuf> = files_to_search(&config).unwrap();
+        let mut results: Vec<String> = Vec::new();
+        let stats: Stats = search_files(&config, &searcher, &files, &mut |output: &str| results.push(output.to_string())).unwrap();
+        assert_eq!(vec![format!("{}:to be\n", dir.join("b.txt").display())], results);
+        assert_eq!(1, stats.errors.len());
+        assert!(stats.errors[0].contains("a.pdf: preprocessor `false` failed"));
+        assert!(run(config).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn broken_archives_are_errors_for_their_file_only() {
+        use flate2::Compression;
+        use flate2::write::GzEncoder;
+        use std::io::Write;
+
+        let dir: PathBuf = test_util::scratch_dir("broken-archive", &[("a.txt", "needle\n")]);
+        let gzip = |text: &str| {
+            let mut gzip: GzEncoder<Vec<u8>> = GzEncoder::new(Vec::new(), Compression::default());
+            gzip.write_all(text.as_bytes()).unwrap();
+            return gzip.finish().unwrap();
+        };
+        fs::write(dir.join("good.log.gz"), gzip("needle\n")).unwrap();
+        let long: String = (0..5000).map(|i| format!("line {i}\n")).collect();
+        let whole: Vec<u8> = gzip(&long);
+        fs::write(dir.join("truncated.log.gz"), &whole[..whole.len() / 2]).unwrap();
+
+        let args: Vec<String> = ["minigrep", "-Z", "needle"].iter().map(|s| s.to_string()).chain([dir.display().to_string()]).collect();
+        let config: Config = Config::build(&args).unwrap();
+        let searcher: Searcher = config.searcher().unwrap();
+        let files: Vec<PathBuf> = files_to_search(&config).unwrap();
+        let mut results: Vec<String> = Vec::new();
+        let stats: Stats = search_files(&config, &searcher, &files, &mut |output: &str| results.push(output.to_string())).unwrap();
+        assert_eq!(2, results.len(), "{results:?}");
+        assert_eq!(1, stats.errors.len());
+        assert!(stats.errors[0].contains("truncated.log.gz: "), "{}", stats.errors[0]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn format_gets_real_line_numbers_and_checks_groups() {
+        let log: &str = "2024-01-01T00:00:00 start\n2024-01-02T00:00:00 error one\n2024-01-03T00:00:00 error two\n";
+        let dir: PathBuf = test_util::scratch_dir("format-lines", &[("app.log", log)]);
+        let path: String = dir.join("app.log").display().to_string();
+        let args: Vec<String> = ["minigrep", "--sorted", "--since", "2024-01-03", "--format", "{line_number}", "error", &path]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let config: Config = Config::build(&args).unwrap();
+        let mut results: Vec<String> = Vec::new();
+        search_files(&config, &config.searcher().unwrap(), &[dir.join("app.log")], &mut |output: &str| results.push(output.to_string()))
+            .unwrap();
+        assert_eq!(vec![String::from("3\n")], results);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let args: Vec<String> = ["minigrep", "--boolean", "--regex", "--format", "{1}", "(a) AND b", "x"].iter().map(|s| s.to_string()).collect();
+        assert!(Config::build(&args).unwrap().check_format().is_err());
+    }
+
+    #[test]
+    fn files_defaults_to_current_directory() {
+        let args: Vec<String> = ["minigrep", "--files", "-0"].iter().map(|s| s.to_string()).collect();
+        let config: Config = Config::build(&args).unwrap();
+        assert_eq!((Command::Files, ".", true), (config.command, config.file_path.as_str(), config.null));
+    }
+
+    #[test]
+    fn index_keeps_files_whose_decoded_fields_match() {
+        let dir: PathBuf = test_util::scratch_dir("index-jsonl", &[("a.jsonl", "{\"msg\": \"caf\\u00e9 \\\"ok\\\"\"}\n"), ("b.txt", "tea")]);
+        for query in ["café", "\"ok\""] {
+            let args: Vec<String> =
+                ["minigrep", "--index", "--jsonl", query].iter().map(|s| s.to_string()).chain([dir.display().to_string()]).collect();
+            let config: Config = Config::build(&args).unwrap();
+            assert!(files_to_search(&config).unwrap().contains(&dir.join("a.jsonl")), "{query}");
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn config_file_goes_under_profile_and_command_line() {
+        let dir: PathBuf = test_util::scratch_dir(
+            "config-merge",
+            &[
+                ("minigrep.conf", "line-number = true\nignore-case = true\ntop = 5\n[profile.logs]\nregex = true\nignore-case = false\ntop = 7\n"),
+                ("xdg/minigrep/config", "stats = true\n"),
+            ],
+        );
+        let file: OsString = dir.join("minigrep.conf").into_os_string();
+        let var = |name: &str| match name {
+            config_file::CONFIG_ENV_VAR => Some(file.clone()),
+            "XDG_CONFIG_HOME" => Some(dir.join("xdg").into_os_string()),
+            _ => None,
+        };
+        let load = |args: &[&str]| {
+            let args: Vec<String> = ["minigrep"].iter().chain(args).chain(&["to", "poem.txt"]).map(|s| s.to_string()).collect();
+            return Config::load_from(&args, var);
+        };
+        let config: Config = load(&[]).unwrap();
+        assert_eq!((true, true, false, 5, false), (config.line_number, config.ignore_case, config.regex, config.top, config.stats));
+        let config: Config = load(&["--profile", "logs"]).unwrap();
+        assert_eq!((true, false, true, 7), (config.line_number, config.ignore_case, config.regex, config.top));
+        let config: Config = load(&["--profile", "logs", "-i", "--top", "9", "--no-line-number"]).unwrap();
+        assert_eq!((false, true, true, 9), (config.line_number, config.ignore_case, config.regex, config.top));
+        assert!(load(&["--profile", "missing"]).unwrap_err().to_string().contains("missing"));
+        assert!(!load(&["--no-config"]).unwrap().line_number);
+
+        // Without MINIGREP_CONFIG the file in the config directory is used.
+        let args: Vec<String> = ["minigrep", "to", "poem.txt"].iter().map(|s| s.to_string()).collect();
+        let config: Config = Config::load_from(&args, |name| if name == config_file::CONFIG_ENV_VAR { None } else { var(name) }).unwrap();
+        assert_eq!((true, false), (config.stats, config.line_number));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn bad_config_files_are_reported() {
+        let dir: PathBuf = test_util::scratch_dir(
+            "config-errors",
+            &[("malformed.conf", "regex = true\nnot a setting\n"), ("command.conf", "files = true\n")],
+        );
+        let args: Vec<String> = ["minigrep", "to", "poem.txt"].iter().map(|s| s.to_string()).collect();
+        let load = |name: &str| {
+            let file: OsString = dir.join(name).into_os_string();
+            return Config::load_from(&args, |var| (var == config_file::CONFIG_ENV_VAR).then(|| file.clone())).map(|_| ());
+        };
+        let err: String = load("malformed.conf").unwrap_err().to_string();
+        assert!(err.contains("malformed.conf") && err.contains("line 2"), "{err}");
+        assert!(load("command.conf").unwrap_err().to_string().contains("`files`"));
+        // A file named by MINIGREP_CONFIG has to exist.
+        assert!(load("missing.conf").is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn follow_rejects_options_it_cant_apply() {
+        let build = |options: &[&str]| {
+            let args: Vec<String> = ["minigrep", "--follow"].iter().chain(options).chain(&["to", "app.log"]).map(|s| s.to_string()).collect();
+            return Config::build(&args).map(|config| config.follow);
+        };
+        assert_eq!(Ok(true), build(&["-n", "--format", "{line}"]));
+        for options in [
+            &["--jsonl", "--field", "msg"][..],
+            &["--csv"],
+            &["--since", "2024-05-01"],
+            &["--near", "3"],
+            &["-U"],
+            &["--paragraph"],
+            &["-z"],
+            &["--pre", "cat"],
+        ] {
+            assert!(build(options).unwrap_err().contains("--follow"), "{options:?}");
+        }
+        assert_eq!(Ok(false), build(&["--csv", "--no-follow"]));
+    }
+
+    #[test]
+    fn double_dash_allows_queries_starting_with_a_dash() {
+        let args: Vec<String> = ["minigrep", "-x", "poem.txt"].iter().map(|s| s.to_string()).collect();
+        assert!(Config::build(&args).unwrap_err().contains("--"));
+        let args: Vec<String> = ["minigrep", "-i", "--", "-x", "poem.txt"].iter().map(|s| s.to_string()).collect();
+        let config: Config = Config::build(&args).unwrap();
+        assert_eq!(("-x", true), (config.query.as_str(), config.ignore_case));
+    }
+
+    #[test]
+    fn build_reads_flags() {
+        let args: Vec<String> = ["minigrep", "-i", "to", "poem.txt"].iter().map(|s| s.to_string()).collect();
+        let config: Config = Config::build(&args).unwrap();
+        assert!(config.ignore_case);
+        assert_eq!("to", config.query);
+        assert_eq!("poem.txt", config.file_path);
+    }
+}
@@ -1,18 +1,22 @@
 use std::env;
+use std::error::Error;
 use std::process;
 
-use ch12_cli::Config;
+use ch12_cli::{Command, Config};
 
 
 fn main() {
     let args: Vec<String> = env::args().collect();
     //let config = Config::new(&args);
-    let config: Config = Config::build(&args).unwrap_or_else(|err: &str| {
+    let config: Config = Config::load(&args).unwrap_or_else(|err: Box<dyn Error>| {
         println!("Problem parsing arguments: {err}");
         process::exit(1);
     });
-    println!("Searching for {}", config.query);
-    println!("In file {}", config.file_path);
+    // The banner goes to stderr so it never ends up in --format, -0 or other piped output.
+    if config.command == Command::Search {
+        eprintln!("Searching for {}", config.query);
+        eprintln!("In file {}", config.file_path);
+    }
 
     if let Err(e) = ch12_cli::run(config) {
         println!("Application error: {e}");
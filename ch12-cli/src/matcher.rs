@@ -0,0 +1,351 @@
+// A Matcher knows how to find a query inside a piece of text. The searcher only ever talks to
+// the trait, so the same line splitting and output code works no matter how the query is
+// interpreted: as a literal, ignoring case, as a regex, or as any of several patterns.
+
+use std::error::Error;
+
+use regex::{Regex, RegexBuilder};
+use regex_syntax::ast::{self, Ast, ClassSet, ClassSetItem};
+
+use crate::normalize::{self, Normalization, NormalizingMatcher};
+
+/// The byte range of one match inside the text that was searched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Match {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Match {
+    pub fn new(start: usize, end: usize) -> Match {
+        return Match { start, end };
+    }
+}
+
+/// The capture groups of one match: `groups[0]` is the whole match, and groups that didn't take
+/// part in it are None. `names` gives the index of each named group.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Groups {
+    pub groups: Vec<Option<Match>>,
+    pub names: Vec<(String, usize)>,
+}
+
+pub trait Matcher: Send + Sync {
+    /// Returns the first match in `haystack` that starts at or after the byte offset `at`.
+    fn find_at(&self, haystack: &str, at: usize) -> Option<Match>;
+
+    fn find(&self, haystack: &str) -> Option<Match> {
+        return self.find_at(haystack, 0);
+    }
+
+    fn is_match(&self, haystack: &str) -> bool {
+        return self.find(haystack).is_some();
+    }
+
+    /// Returns every non-overlapping match in `haystack`, left to right.
+    fn find_all(&self, haystack: &str) -> Vec<Match> {
+        let mut matches: Vec<Match> = Vec::new();
+        let mut at: usize = 0;
+        while at <= haystack.len() {
+            let found: Match = match self.find_at(haystack, at) {
+                Some(m) => m,
+                None => break,
+            };
+            matches.push(found);
+            // An empty match would be found again at the same spot, so step over one char.
+            at = if found.end > found.start {
+                found.end
+            } else {
+                next_char_boundary(haystack, found.end)
+            };
+        }
+        return matches;
+    }
+
+    /// The capture groups of `found`, a match this matcher returned for `haystack`. Only
+    /// regexes have groups; other matchers return None.
+    fn groups(&self, _haystack: &str, _found: Match) -> Option<Groups> {
+        return None;
+    }
+}
+
+/// The options that decide how a single pattern is turned into a Matcher.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MatchOptions {
+    pub ignore_case: bool,
+    /// Decide case sensitivity from each pattern instead: ignore case unless the pattern has an
+    /// uppercase letter in it. Overrides `ignore_case`.
+    pub smart_case: bool,
+    pub regex: bool,
+    pub normalization: Normalization,
+}
+
+impl MatchOptions {
+    /// Whether `pattern` is matched ignoring case. With smart case this is decided for each
+    /// pattern (each term of a boolean query) on its own.
+    pub fn ignores_case(&self, pattern: &str) -> bool {
+        if self.smart_case {
+            return !has_uppercase(pattern, self.regex);
+        }
+        return self.ignore_case;
+    }
+
+    pub fn build(&self, pattern: &str) -> Result<Box<dyn Matcher>, Box<dyn Error>> {
+        if self.smart_case {
+            let ignore_case: bool = self.ignores_case(pattern);
+            return MatchOptions { ignore_case, smart_case: false, ..*self }.build(pattern);
+        }
+        if self.normalization != Normalization::None {
+            let pattern: String = normalize::normalize_pattern(pattern, self.normalization);
+            let inner: Box<dyn Matcher> = MatchOptions { normalization: Normalization::None, ..*self }.build(&pattern)?;
+            return Ok(Box::new(NormalizingMatcher::new(inner, self.normalization)));
+        }
+        if self.regex {
+            return Ok(Box::new(RegexMatcher::new(pattern, self.ignore_case)?));
+        }
+        if self.ignore_case {
+            return Ok(Box::new(CaseInsensitiveMatcher::new(pattern)));
+        }
+        return Ok(Box::new(LiteralMatcher::new(pattern)));
+    }
+}
+
+/// Whether smart case should make `pattern` case-sensitive. In a regex only literal characters
+/// count, so `\W+` or `\p{Lu}` or `(?P<Name>x)` are still all lowercase, while a literal
+/// inside a character class like `[A-Z]` does count.
+pub fn has_uppercase(pattern: &str, regex: bool) -> bool {
+    if !regex {
+        return pattern.chars().any(char::is_uppercase);
+    }
+    return match ast::parse::Parser::new().parse(pattern) {
+        Ok(parsed) => ast_has_uppercase(&parsed),
+        // Building the regex will report the error; until then look at every character.
+        Err(_) => pattern.chars().any(char::is_uppercase),
+    };
+}
+
+fn ast_has_uppercase(ast: &Ast) -> bool {
+    return match ast {
+        Ast::Literal(literal) => literal.c.is_uppercase(),
+        Ast::ClassBracketed(class) => class_has_uppercase(&class.kind),
+        Ast::Repetition(repetition) => ast_has_uppercase(&repetition.ast),
+        Ast::Group(group) => ast_has_uppercase(&group.ast),
+        Ast::Alternation(alternation) => alternation.asts.iter().any(ast_has_uppercase),
+        Ast::Concat(concat) => concat.asts.iter().any(ast_has_uppercase),
+        _ => false,
+    };
+}
+
+fn class_has_uppercase(set: &ClassSet) -> bool {
+    return match set {
+        ClassSet::Item(item) => class_item_has_uppercase(item),
+        ClassSet::BinaryOp(op) => class_has_uppercase(&op.lhs) || class_has_uppercase(&op.rhs),
+    };
+}
+
+fn class_item_has_uppercase(item: &ClassSetItem) -> bool {
+    return match item {
+        ClassSetItem::Literal(literal) => literal.c.is_uppercase(),
+        ClassSetItem::Range(range) => range.start.c.is_uppercase() || range.end.c.is_uppercase(),
+        ClassSetItem::Bracketed(class) => class_has_uppercase(&class.kind),
+        ClassSetItem::Union(union) => union.items.iter().any(class_item_has_uppercase),
+        _ => false,
+    };
+}
+
+pub(crate) fn next_char_boundary(text: &str, at: usize) -> usize {
+    let mut next: usize = at + 1;
+    while next < text.len() && !text.is_char_boundary(next) {
+        next += 1;
+    }
+    return next;
+}
+
+/// Matches the query exactly, byte for byte.
+pub struct LiteralMatcher {
+    needle: String,
+}
+
+impl LiteralMatcher {
+    pub fn new(needle: &str) -> LiteralMatcher {
+        return LiteralMatcher { needle: needle.to_string() };
+    }
+}
+
+impl Matcher for LiteralMatcher {
+    fn find_at(&self, haystack: &str, at: usize) -> Option<Match> {
+        let start: usize = haystack.get(at..)?.find(&self.needle)? + at;
+        return Some(Match::new(start, start + self.needle.len()));
+    }
+}
+
+/// Matches the query ignoring case. Offsets refer to the original text, not a lowercased copy,
+/// so they stay correct even when lowercasing changes the length of a character.
+pub struct CaseInsensitiveMatcher {
+    needle: Vec<char>,
+}
+
+impl CaseInsensitiveMatcher {
+    pub fn new(needle: &str) -> CaseInsensitiveMatcher {
+        return CaseInsensitiveMatcher { needle: needle.to_lowercase().chars().collect() };
+    }
+
+    // Returns the end offset if the needle matches the text starting at `start`.
+    fn match_here(&self, haystack: &str, start: usize) -> Option<usize> {
+        let mut matched: usize = 0;
+        for (offset, c) in haystack[start..].char_indices() {
+            if matched == self.needle.len() {
+                return Some(start + offset);
+            }
+            for lower in c.to_lowercase() {
+                if matched == self.needle.len() || self.needle[matched] != lower {
+                    return None;
+                }
+                matched += 1;
+            }
+        }
+        if matched == self.needle.len() {
+            return Some(haystack.len());
+        }
+        return None;
+    }
+}
+
+impl Matcher for CaseInsensitiveMatcher {
+    fn find_at(&self, haystack: &str, at: usize) -> Option<Match> {
+        let rest: &str = haystack.get(at..)?;
+        for (offset, _) in rest.char_indices().chain(std::iter::once((rest.len(), ' '))) {
+            let start: usize = at + offset;
+            if let Some(end) = self.match_here(haystack, start) {
+                return Some(Match::new(start, end));
+            }
+        }
+        return None;
+    }
+}
+
+/// Matches a regular expression.
+pub struct RegexMatcher {
+    regex: Regex,
+}
+
+impl RegexMatcher {
+    pub fn new(pattern: &str, ignore_case: bool) -> Result<RegexMatcher, Box<dyn Error>> {
+        // ^ and $ match at line boundaries, which only matters when searching across lines.
+        let regex: Regex = RegexBuilder::new(pattern)
+            .case_insensitive(ignore_case)
+            .multi_line(true)
+            .build()?;
+        return Ok(RegexMatcher { regex });
+    }
+
+    pub fn regex(&self) -> &Regex {
+        return &self.regex;
+    }
+}
+
+impl Matcher for RegexMatcher {
+    fn find_at(&self, haystack: &str, at: usize) -> Option<Match> {
+        if at > haystack.len() {
+            return None;
+        }
+        let found: regex::Match = self.regex.find_at(haystack, at)?;
+        return Some(Match::new(found.start(), found.end()));
+    }
+
+    fn groups(&self, haystack: &str, found: Match) -> Option<Groups> {
+        let captures: regex::Captures = self.regex.captures_at(haystack, found.start)?;
+        let groups: Vec<Option<Match>> = captures.iter().map(|m| m.map(|m| Match::new(m.start(), m.end()))).collect();
+        let names: Vec<(String, usize)> = self
+            .regex
+            .capture_names()
+            .enumerate()
+            .filter_map(|(index, name)| Some((name?.to_string(), index)))
+            .collect();
+        return Some(Groups { groups, names });
+    }
+}
+
+/// Matches whichever of several matchers matches first. When two start at the same place the
+/// longer match wins.
+pub struct AnyMatcher {
+    matchers: Vec<Box<dyn Matcher>>,
+}
+
+impl AnyMatcher {
+    pub fn new(matchers: Vec<Box<dyn Matcher>>) -> AnyMatcher {
+        return AnyMatcher { matchers };
+    }
+}
+
+impl Matcher for AnyMatcher {
+    fn find_at(&self, haystack: &str, at: usize) -> Option<Match> {
+        let mut best: Option<Match> = None;
+        for matcher in &self.matchers {
+            if let Some(found) = matcher.find_at(haystack, at) {
+                best = match best {
+                    Some(b) if b.start < found.start => Some(b),
+                    Some(b) if b.start == found.start && b.end >= found.end => Some(b),
+                    _ => Some(found),
+                };
+            }
+        }
+        return best;
+    }
+
+    fn groups(&self, haystack: &str, found: Match) -> Option<Groups> {
+        let matcher: &dyn Matcher = self.matchers.iter().find(|m| m.find_at(haystack, found.start) == Some(found))?.as_ref();
+        return matcher.groups(haystack, found);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn literal_finds_all() {
+        let matcher: LiteralMatcher = LiteralMatcher::new("ab");
+        assert_eq!(
+            vec![Match::new(0, 2), Match::new(3, 5)],
+            matcher.find_all("ab ab")
+        );
+    }
+
+    #[test]
+    fn case_insensitive_offsets_are_in_original_text() {
+        let matcher: CaseInsensitiveMatcher = CaseInsensitiveMatcher::new("rust");
+        // 'İ' lowercases to two chars, which must not shift the reported offsets.
+        let haystack: &str = "İ TRUST";
+        let found: Match = matcher.find(haystack).unwrap();
+        assert_eq!("RUST", &haystack[found.start..found.end]);
+    }
+
+    #[test]
+    fn any_prefers_leftmost_then_longest() {
+        let matcher: AnyMatcher = AnyMatcher::new(vec![
+            Box::new(LiteralMatcher::new("fast")),
+            Box::new(LiteralMatcher::new("safe")),
+            Box::new(LiteralMatcher::new("saf")),
+        ]);
+        assert_eq!(Some(Match::new(0, 4)), matcher.find("safe, fast"));
+    }
+
+    #[test]
+    fn smart_case_counts_only_literals() {
+        assert!(!has_uppercase("rust", false));
+        assert!(has_uppercase("Rust", false));
+        assert!(!has_uppercase(r"\W+\p{Lu}(?P<Name>x)\b", true));
+        assert!(has_uppercase(r"(?i:\d)[A-Z]", true));
+        assert!(has_uppercase(r"fo\x41", true));
+        let options: MatchOptions = MatchOptions { smart_case: true, ..MatchOptions::default() };
+        assert!(options.build("rust").unwrap().is_match("Trust me."));
+        assert!(!options.build("Rust").unwrap().is_match("trust me."));
+    }
+
+    #[test]
+    fn regex_matches() {
+        let matcher: RegexMatcher = RegexMatcher::new(r"p\w+ive", true).unwrap();
+        assert!(matcher.is_match("safe, fast, PRODUCTIVE."));
+    }
+}
@@ -0,0 +1,202 @@
+// The commands and modes other than a plain search: `index`, `serve`, --files,
+// --print-config, --interactive, and the long-running --follow, --watch and --rank. Each gets
+// its own function; `run` in the crate root only does the search itself.
+
+use std::error::Error;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use crate::follow::{self, Follower};
+use crate::index::{Index, UpdateSummary};
+use crate::matcher::Match;
+use crate::rank::{self, RankCache, Ranked, Unit};
+use crate::records::Terminator;
+use crate::searcher::{LineMatch, Searcher};
+use crate::serve::{self, Listener};
+use crate::stats::Stats;
+use crate::template::Fields;
+use crate::watch::{self, Snapshot};
+use crate::{Command, Config, files_to_search, format_match, interactive, search_files, walk};
+
+/// Whether `config` asks for something other than a plain search.
+pub(crate) fn applies(config: &Config) -> bool {
+    return config.command != Command::Search || config.follow || config.watch || config.rank;
+}
+
+pub(crate) fn run(config: Config) -> Result<(), Box<dyn Error>> {
+    match config.command {
+        Command::Index => return run_index(&config),
+        Command::PrintConfig => {
+            println!("{config:#?}");
+            return Ok(());
+        }
+        _ => {}
+    }
+    config.check_format()?;
+    match config.command {
+        Command::Interactive => return interactive::run(config),
+        Command::Serve => return run_serve(&config),
+        Command::Files => return run_files(&config),
+        _ => {}
+    }
+    if config.follow {
+        return run_follow(&config);
+    }
+    if config.watch {
+        return run_watch(&config);
+    }
+    return run_rank(&config);
+}
+
+fn run_serve(config: &Config) -> Result<(), Box<dyn Error>> {
+    let listener: Listener = Listener::bind(config.listen.as_deref().unwrap_or(serve::DEFAULT_LISTEN))?;
+    // The server only stops when it's interrupted, and accepting blocks, so Ctrl-C removes the
+    // socket file itself before exiting rather than setting a flag nobody checks.
+    if let Some(socket) = listener.socket_path() {
+        let socket: PathBuf = socket.to_path_buf();
+        ctrlc::set_handler(move || {
+            let _ = fs::remove_file(&socket);
+            std::process::exit(130);
+        })?;
+    }
+    println!("Listening on {} for searches in {}", listener.address(), config.file_path);
+    return listener.serve(Path::new(&config.file_path));
+}
+
+fn run_files(config: &Config) -> Result<(), Box<dyn Error>> {
+    let end: char = if config.null { '\0' } else { '\n' };
+    for path in walk::walk_filtered(Path::new(&config.file_path), &config.file_filter()?)? {
+        print!("{}{end}", path.display());
+    }
+    return Ok(());
+}
+
+// Returns a flag that is set when the user presses Ctrl-C, so long-running modes can stop
+// cleanly instead of being killed mid-write.
+fn stop_on_ctrl_c() -> Result<Arc<AtomicBool>, Box<dyn Error>> {
+    let stop: Arc<AtomicBool> = Arc::new(AtomicBool::new(false));
+    let handler_stop: Arc<AtomicBool> = Arc::clone(&stop);
+    ctrlc::set_handler(move || handler_stop.store(true, Ordering::SeqCst))?;
+    return Ok(stop);
+}
+
+fn run_follow(config: &Config) -> Result<(), Box<dyn Error>> {
+    let path: &Path = Path::new(&config.file_path);
+    if path.is_dir() {
+        return Err("--follow needs a file, not a directory".into());
+    }
+    let searcher: Searcher = config.searcher()?;
+    let mut follower: Follower = Follower::open(path, config.needs_line_numbers())?;
+    let stop: Arc<AtomicBool> = stop_on_ctrl_c()?;
+    follow::follow(&mut follower, Duration::from_millis(config.poll_interval), &stop, |line_number, line| {
+        let matches: Vec<Match> = searcher.matcher().find_all(line);
+        if !matches.is_empty() {
+            print!("{}", format_match(config, &searcher, None, &LineMatch { line_number, line, matches }, 0));
+        }
+    })?;
+    return Ok(());
+}
+
+fn run_watch(config: &Config) -> Result<(), Box<dyn Error>> {
+    let searcher: Searcher = config.searcher()?;
+    let interval: Duration = Duration::from_millis(config.poll_interval);
+    let stop: Arc<AtomicBool> = stop_on_ctrl_c()?;
+    let mut previous: Option<Vec<String>> = None;
+    loop {
+        // Re-list the files on every run so new files under a directory are picked up too.
+        let files: Vec<PathBuf> = files_to_search(config)?;
+        let snapshot: Snapshot = Snapshot::take(&files);
+        let mut results: Vec<String> = Vec::new();
+        let stats: Stats = search_files(config, &searcher, &files, &mut |output: &str| results.push(output.to_string()))?;
+        print!("\x1b[2J\x1b[H");
+        println!(
+            "Watching {} for {:?}: {} matching lines in {} of {} files (Ctrl-C to stop)",
+            config.file_path, config.query, stats.matched_lines, stats.files_matched, stats.files_searched
+        );
+        println!();
+        for output in &results {
+            print!("{output}");
+        }
+        if let Some(previous) = &previous {
+            let (added, removed): (Vec<&str>, Vec<&str>) = watch::diff(previous, &results);
+            println!();
+            println!("Since the last run: {} added, {} removed", added.len(), removed.len());
+            for output in added {
+                println!("+ {}", output.trim_end_matches(['\n', '\0']));
+            }
+            for output in removed {
+                println!("- {}", output.trim_end_matches(['\n', '\0']));
+            }
+        }
+        // Files that failed this run are shown with the results, and watching carries on.
+        for error in &stats.errors {
+            println!();
+            println!("{error}");
+        }
+        io::stdout().flush()?;
+        previous = Some(results);
+        let changed: bool = watch::wait_for_change(&snapshot, interval, &stop, || {
+            return Ok(Snapshot::take(&files_to_search(config)?));
+        })?;
+        if !changed {
+            return Ok(());
+        }
+    }
+}
+
+fn run_rank(config: &Config) -> Result<(), Box<dyn Error>> {
+    if config.regex || config.boolean {
+        return Err("--rank can't be combined with --regex or --boolean".into());
+    }
+    let terms: Vec<String> = rank::tokenize(&config.query);
+    if terms.is_empty() {
+        return Err("--rank needs a query with at least one word".into());
+    }
+    let root: &Path = Path::new(&config.file_path);
+    // The trigram index only finds files containing the whole query, which is too strict for
+    // ranking, so every file the filters allow is scored.
+    let files: Vec<PathBuf> = walk::walk_filtered(root, &config.file_filter()?)?;
+    let base: &Path = if root.is_dir() { root } else { root.parent().unwrap_or(Path::new("")) };
+    let mut cache: RankCache = if root.is_dir() { RankCache::open(root) } else { RankCache::default() };
+    let changed: usize = cache.update(base, &files)?;
+    if root.is_dir() && (changed > 0 || !RankCache::cache_path(root).exists()) {
+        cache.save(root)?;
+    }
+    let unit: Unit = if config.terminator == Terminator::Paragraph { Unit::Paragraph } else { Unit::File };
+    for ranked in cache.rank(base, &terms, unit, config.top) {
+        let Ranked { path, line, score } = ranked;
+        let contents: String = fs::read_to_string(&path)?;
+        let snippet: Option<(usize, &str)> = rank::snippet(&contents, line, unit, &terms);
+        if let Some(template) = &config.format {
+            // The snippet stands in for the matching line, and all of it is the match.
+            let (line_number, line): (usize, &str) = snippet.map_or((line, ""), |(n, snippet)| (n, snippet.trim()));
+            let name: String = path.display().to_string();
+            print!("{}", template.render(&Fields { path: &name, line_number, line, matched: Match::new(0, line.len()), groups: None }));
+            continue;
+        }
+        match snippet {
+            Some((line_number, snippet)) => println!("{score:>8.3}  {}:{line_number}: {}", path.display(), snippet.trim()),
+            None => println!("{score:>8.3}  {}:{line}", path.display()),
+        }
+    }
+    return Ok(());
+}
+
+fn run_index(config: &Config) -> Result<(), Box<dyn Error>> {
+    let root: &Path = Path::new(&config.file_path);
+    if !root.is_dir() {
+        return Err(format!("{} is not a directory", root.display()).into());
+    }
+    let mut index: Index = Index::open(root)?;
+    let summary: UpdateSummary = index.update(root)?;
+    index.save(root)?;
+    println!(
+        "Indexed {} files ({} updated, {} removed)",
+        summary.files, summary.reindexed, summary.removed
+    );
+    return Ok(());
+}
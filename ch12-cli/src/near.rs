@@ -0,0 +1,191 @@
+// Proximity search: finds places where every word of the query occurs within a window of each
+// other, like "lock within 5 words of timeout" or "panic within 3 lines of thread". The text is
+// split into words (runs of letters, digits and underscores), and a query word matches a whole
+// word, not part of one. The window is measured in words (how far apart the first and last word
+// of a span may be) or in lines (how many lines apart they may be).
+//
+// Occurrences of the query's words are walked in order with a sliding window. For each
+// occurrence the window is shrunk to the shortest stretch ending there that still holds every
+// word, and that stretch is kept if it fits the window. Spans don't overlap: of two that would,
+// the shorter one wins, and the earlier one on a tie.
+
+use std::error::Error;
+
+use crate::matcher::MatchOptions;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Window {
+    Words(usize),
+    Lines(usize),
+}
+
+/// A stretch of the text holding every query word. `start..end` are byte offsets: the first
+/// and last word for word windows, the whole lines for line windows. Lines count from 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub first_line: usize,
+    pub last_line: usize,
+}
+
+pub struct Near {
+    terms: Vec<String>,
+    window: Window,
+    /// For each term, whether it matches ignoring case (lowercased in `terms` if so).
+    ignore_case: Vec<bool>,
+}
+
+struct Occurrence {
+    term: usize,
+    position: usize,
+    start: usize,
+    end: usize,
+    line: usize,
+}
+
+impl Near {
+    /// The query is the words to look for, separated by spaces; there must be at least two
+    /// different ones. Each word ignores case or not as `options` would decide for it alone.
+    pub fn new(query: &str, window: Window, options: &MatchOptions) -> Result<Near, Box<dyn Error>> {
+        let mut terms: Vec<String> = Vec::new();
+        let mut ignore_case: Vec<bool> = Vec::new();
+        for word in query.split_whitespace() {
+            let ignores: bool = options.ignores_case(word);
+            let word: String = if ignores { word.to_lowercase() } else { word.to_string() };
+            if !terms.contains(&word) {
+                terms.push(word);
+                ignore_case.push(ignores);
+            }
+        }
+        if terms.len() < 2 {
+            return Err("proximity search needs at least two different words".into());
+        }
+        return Ok(Near { terms, window, ignore_case });
+    }
+
+    pub fn terms(&self) -> &[String] {
+        return &self.terms;
+    }
+
+    pub fn find(&self, text: &str) -> Vec<Span> {
+        let occurrences: Vec<Occurrence> = self.occurrences(text);
+        let limit: usize = match self.window {
+            Window::Words(n) | Window::Lines(n) => n,
+        };
+        let mut counts: Vec<usize> = vec![0; self.terms.len()];
+        let mut covered: usize = 0;
+        let mut left: usize = 0;
+        // The spans found so far, as (first occurrence, last occurrence, length).
+        let mut picked: Vec<(usize, usize, usize)> = Vec::new();
+        for (right, occurrence) in occurrences.iter().enumerate() {
+            counts[occurrence.term] += 1;
+            if counts[occurrence.term] == 1 {
+                covered += 1;
+            }
+            if covered < self.terms.len() {
+                continue;
+            }
+            while counts[occurrences[left].term] > 1 {
+                counts[occurrences[left].term] -= 1;
+                left += 1;
+            }
+            let length: usize = occurrence.position - occurrences[left].position;
+            if length > limit {
+                continue;
+            }
+            match picked.last() {
+                Some(&(_, last_right, _)) if left <= last_right => {
+                    if length < picked.last().unwrap().2 {
+                        picked.pop();
+                        picked.push((left, right, length));
+                    }
+                }
+                _ => picked.push((left, right, length)),
+            }
+        }
+        return picked
+            .into_iter()
+            .map(|(left, right, _)| self.span(text, &occurrences[left], &occurrences[right]))
+            .collect();
+    }
+
+    fn occurrences(&self, text: &str) -> Vec<Occurrence> {
+        let mut occurrences: Vec<Occurrence> = Vec::new();
+        let mut line: usize = 1;
+        let mut word_start: Option<usize> = None;
+        let mut words: usize = 0;
+        for (i, c) in text.char_indices().chain([(text.len(), '\n')]) {
+            if c.is_alphanumeric() || c == '_' {
+                word_start.get_or_insert(i);
+                continue;
+            }
+            if let Some(start) = word_start.take() {
+                let word: &str = &text[start..i];
+                let lowercase: String = word.to_lowercase();
+                let found: Option<usize> = self
+                    .terms
+                    .iter()
+                    .zip(&self.ignore_case)
+                    .position(|(term, &ignores)| if ignores { *term == lowercase } else { term == word });
+                if let Some(term) = found {
+                    let position: usize = if let Window::Lines(_) = self.window { line } else { words };
+                    occurrences.push(Occurrence { term, position, start, end: i, line });
+                }
+                words += 1;
+            }
+            if c == '\n' {
+                line += 1;
+            }
+        }
+        return occurrences;
+    }
+
+    fn span(&self, text: &str, first: &Occurrence, last: &Occurrence) -> Span {
+        let (start, end) = match self.window {
+            Window::Words(_) => (first.start, last.end),
+            Window::Lines(_) => {
+                let start: usize = text[..first.start].rfind('\n').map_or(0, |i| i + 1);
+                let end: usize = text[last.end..].find('\n').map_or(text.len(), |i| last.end + i);
+                (start, end)
+            }
+        };
+        return Span { start, end, first_line: first.line, last_line: last.line };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts<'a>(near: &Near, text: &'a str) -> Vec<&'a str> {
+        return near.find(text).iter().map(|span| &text[span.start..span.end]).collect();
+    }
+
+    #[test]
+    fn words_within_the_window() {
+        let near: Near = Near::new("lock timeout", Window::Words(4), &MatchOptions::default()).unwrap();
+        let text: &str = "the lock waited for a timeout\nanother timeout, then lock\nlocking timeouts";
+        assert_eq!(vec!["lock waited for a timeout", "timeout, then lock"], texts(&near, text));
+        assert_eq!(vec!["timeout lock"], texts(&near, "lock a timeout lock"));
+        let spans: Vec<Span> = near.find("a lock\nand timeout");
+        assert_eq!((1, 2), (spans[0].first_line, spans[0].last_line));
+        assert!(Near::new("lock lock", Window::Words(3), &MatchOptions::default()).is_err());
+    }
+
+    #[test]
+    fn lines_and_more_than_two_terms() {
+        let options: MatchOptions = MatchOptions { ignore_case: true, ..MatchOptions::default() };
+        let near: Near = Near::new("panic thread main", Window::Lines(2), &options).unwrap();
+        let text: &str = "Thread started\nnothing\nmain loop\nPANIC here\n\n\nthread\nmain\nin a panic\n";
+        let spans: Vec<Span> = near.find(text);
+        assert_eq!(1, spans.len());
+        assert_eq!((7, 9), (spans[0].first_line, spans[0].last_line));
+        assert_eq!("thread\nmain\nin a panic", &text[spans[0].start..spans[0].end]);
+
+        // With smart case only the word with a capital is matched exactly.
+        let options: MatchOptions = MatchOptions { smart_case: true, ..MatchOptions::default() };
+        let near: Near = Near::new("PANIC thread", Window::Lines(0), &options).unwrap();
+        assert_eq!(vec!["Thread PANIC"], texts(&near, "Thread PANIC\npanic thread\n"));
+    }
+}
@@ -0,0 +1,205 @@
+// Unicode-aware matching. The same text can be encoded in more than one way: "é" is either the
+// single code point U+00E9 (NFC) or "e" followed by the combining accent U+0301 (NFD). Byte
+// comparison treats those as different, so a NormalizingMatcher runs its inner matcher on a
+// canonically decomposed (NFD) copy of both the pattern and the text. With accent folding the
+// combining marks are dropped from both as well, so `cafe` finds `café`.
+//
+// Offsets are mapped back to the original text. The text is split into clusters (a starter and
+// the combining marks after it), and every normalized byte remembers the original cluster it
+// came from. A match has to start and end on cluster boundaries, so without folding `cafe`
+// doesn't match the first four characters of a decomposed `café`.
+//
+// Regex patterns are decomposed as text, which is right for literal characters but not for
+// every construct: a class like [é] becomes [e\u{301}] and matches either character alone.
+
+use std::sync::Mutex;
+
+use unicode_normalization::UnicodeNormalization;
+use unicode_normalization::char::{canonical_combining_class, is_combining_mark};
+
+use crate::matcher::{Groups, Match, Matcher};
+
+/// How text is normalized before matching.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Normalization {
+    /// Compare the text exactly as it is.
+    #[default]
+    None,
+    /// Treat canonically equivalent text (NFC and NFD forms) as equal.
+    Canonical,
+    /// Like Canonical, and also ignore accents and other combining marks.
+    FoldAccents,
+}
+
+/// A normalized copy of some text, with the original byte range of the cluster behind each of
+/// its bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Normalized {
+    pub text: String,
+    starts: Vec<usize>,
+    ends: Vec<usize>,
+}
+
+impl Normalized {
+    pub fn new(original: &str, normalization: Normalization) -> Normalized {
+        let mut normalized: Normalized = Normalized { text: String::new(), starts: Vec::new(), ends: Vec::new() };
+        let mut chars = original.char_indices().peekable();
+        while let Some((start, _)) = chars.next() {
+            while chars.peek().is_some_and(|&(_, c)| canonical_combining_class(c) != 0) {
+                chars.next();
+            }
+            let end: usize = chars.peek().map(|&(offset, _)| offset).unwrap_or(original.len());
+            let before: usize = normalized.text.len();
+            normalized.text.extend(normalize_chars(&original[start..end], normalization));
+            let added: usize = normalized.text.len() - before;
+            normalized.starts.extend(std::iter::repeat_n(start, added));
+            normalized.ends.extend(std::iter::repeat_n(end, added));
+        }
+        return normalized;
+    }
+
+    fn is_boundary(&self, at: usize) -> bool {
+        return at == 0 || at >= self.text.len() || self.starts[at] != self.starts[at - 1];
+    }
+
+    // The normalized offset of the first cluster starting at or after original offset `at`.
+    fn offset_of(&self, at: usize) -> usize {
+        return self.starts.partition_point(|&start| start < at);
+    }
+
+    // The original byte range behind a normalized match.
+    fn original_match(&self, found: Match, original_len: usize) -> Match {
+        let start: usize = self.starts.get(found.start).copied().unwrap_or(original_len);
+        if found.end == found.start {
+            return Match::new(start, start);
+        }
+        return Match::new(start, self.ends[found.end - 1]);
+    }
+}
+
+fn normalize_chars(text: &str, normalization: Normalization) -> impl Iterator<Item = char> + '_ {
+    let fold: bool = normalization == Normalization::FoldAccents;
+    return text.nfd().filter(move |&c| !(fold && is_combining_mark(c)));
+}
+
+/// Normalizes a pattern the way a NormalizingMatcher normalizes the text it searches.
+pub fn normalize_pattern(pattern: &str, normalization: Normalization) -> String {
+    if normalization == Normalization::None {
+        return pattern.to_string();
+    }
+    return normalize_chars(pattern, normalization).collect();
+}
+
+/// Runs `inner`, built for a normalized pattern, against normalized text.
+pub struct NormalizingMatcher {
+    inner: Box<dyn Matcher>,
+    normalization: Normalization,
+    // The searcher calls find_at over and over on the same line, so the last normalized text is
+    // kept rather than normalizing it again for every match.
+    last: Mutex<Option<(String, Normalized)>>,
+}
+
+impl NormalizingMatcher {
+    pub fn new(inner: Box<dyn Matcher>, normalization: Normalization) -> NormalizingMatcher {
+        return NormalizingMatcher { inner, normalization, last: Mutex::new(None) };
+    }
+}
+
+impl NormalizingMatcher {
+    // The normalized copy of `haystack`, reusing the last one when it's the same text.
+    fn normalized<'a>(&self, last: &'a mut Option<(String, Normalized)>, haystack: &str) -> &'a Normalized {
+        if last.as_ref().is_none_or(|(original, _)| original != haystack) {
+            *last = Some((haystack.to_string(), Normalized::new(haystack, self.normalization)));
+        }
+        return &last.as_ref().unwrap().1;
+    }
+}
+
+impl Matcher for NormalizingMatcher {
+    fn find_at(&self, haystack: &str, at: usize) -> Option<Match> {
+        if at > haystack.len() {
+            return None;
+        }
+        let mut last = self.last.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let normalized: &Normalized = self.normalized(&mut last, haystack);
+        let mut from: usize = normalized.offset_of(at);
+        while let Some(found) = self.inner.find_at(&normalized.text, from) {
+            if normalized.is_boundary(found.start) && normalized.is_boundary(found.end) {
+                return Some(normalized.original_match(found, haystack.len()));
+            }
+            // Only part of a cluster matched; try again from the next character.
+            if found.start >= normalized.text.len() {
+                return None;
+            }
+            from = crate::matcher::next_char_boundary(&normalized.text, found.start);
+        }
+        return None;
+    }
+
+    /// The inner matcher's groups for the same match in the normalized text, each widened to
+    /// the original clusters it covers.
+    fn groups(&self, haystack: &str, found: Match) -> Option<Groups> {
+        let mut last = self.last.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let normalized: &Normalized = self.normalized(&mut last, haystack);
+        let inner: Match = Match::new(normalized.offset_of(found.start), normalized.offset_of(found.end));
+        let groups: Groups = self.inner.groups(&normalized.text, inner)?;
+        return Some(Groups {
+            groups: groups.groups.iter().map(|m| m.map(|m| normalized.original_match(m, haystack.len()))).collect(),
+            names: groups.names,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matcher::MatchOptions;
+
+    const NFC: &str = "un caf\u{e9} cr\u{e8}me";
+    const NFD: &str = "un cafe\u{301} cre\u{300}me";
+
+    fn matcher(pattern: &str, normalization: Normalization) -> Box<dyn Matcher> {
+        return MatchOptions { normalization, ..MatchOptions::default() }.build(pattern).unwrap();
+    }
+
+    #[test]
+    fn nfc_and_nfd_match_each_other() {
+        let found: Match = matcher("caf\u{e9}", Normalization::Canonical).find(NFD).unwrap();
+        assert_eq!("cafe\u{301}", &NFD[found.start..found.end]);
+        let found: Match = matcher("cafe\u{301}", Normalization::Canonical).find(NFC).unwrap();
+        assert_eq!("caf\u{e9}", &NFC[found.start..found.end]);
+        assert!(!matcher("cafe", Normalization::Canonical).is_match(NFD));
+        assert!(!matcher("caf\u{e9}", Normalization::None).is_match(NFD));
+    }
+
+    #[test]
+    fn folding_ignores_accents_and_keeps_offsets() {
+        let folding: Box<dyn Matcher> = matcher("creme", Normalization::FoldAccents);
+        for text in [NFC, NFD] {
+            let found: Match = folding.find(text).unwrap();
+            assert!(text[found.start..found.end].starts_with("cr"));
+            assert_eq!(text.len(), found.end);
+        }
+        let all: Vec<Match> = matcher("e", Normalization::FoldAccents).find_all(NFC);
+        let texts: Vec<&str> = all.iter().map(|m| &NFC[m.start..m.end]).collect();
+        assert_eq!(vec!["\u{e9}", "\u{e8}", "e"], texts);
+    }
+
+    #[test]
+    fn works_with_case_and_regex() {
+        let options: MatchOptions =
+            MatchOptions { ignore_case: true, regex: true, normalization: Normalization::FoldAccents, ..MatchOptions::default() };
+        let found: Match = options.build(r"CAFE\b").unwrap().find(NFC).unwrap();
+        assert_eq!(Match::new(3, 8), found);
+
+        // Capture groups come back as ranges of the original text.
+        let matcher: Box<dyn Matcher> = options.build(r"caf(e) (?P<what>\w+)").unwrap();
+        for (text, e, creme) in [(NFC, "\u{e9}", "cr\u{e8}me"), (NFD, "e\u{301}", "cre\u{300}me")] {
+            let found: Match = matcher.find(text).unwrap();
+            let groups: Groups = matcher.groups(text, found).unwrap();
+            let texts: Vec<&str> = groups.groups.iter().map(|m| m.map_or("", |m| &text[m.start..m.end])).collect();
+            assert_eq!(vec![&text[3..], e, creme], texts);
+            assert_eq!(vec![(String::from("what"), 2)], groups.names);
+        }
+    }
+}
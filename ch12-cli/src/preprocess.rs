@@ -0,0 +1,121 @@
+// Preprocessors for files that need converting to text before they can be searched, like PDFs
+// or office documents. The command is run directly, not through a shell, once per file: it gets
+// the file's path as its only argument and the file on stdin, and whatever it prints to stdout
+// is searched in place of the file. `--pre-glob` limits which files go through it; the rest are
+// read as usual.
+//
+// A command that can't be started or exits unsuccessfully is an error for that file only: the
+// other files are still searched, and the search fails at the end. The error carries what the
+// command wrote to stderr.
+
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+use crate::glob::FileFilter;
+
+#[derive(Debug)]
+pub struct PreprocessError {
+    pub path: PathBuf,
+    pub command: String,
+    /// Why it failed: the exit status, or why the command couldn't be run.
+    pub reason: String,
+    pub stderr: String,
+}
+
+impl fmt::Display for PreprocessError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: preprocessor `{}` {}", self.path.display(), self.command, self.reason)?;
+        let stderr: &str = self.stderr.trim_end();
+        if !stderr.is_empty() {
+            write!(f, ":\n{stderr}")?;
+        }
+        return Ok(());
+    }
+}
+
+impl Error for PreprocessError {}
+
+pub struct Preprocessor {
+    command: String,
+    filter: FileFilter,
+}
+
+impl Preprocessor {
+    /// `globs` pick the files to preprocess, with the same syntax as `--glob` (`!` excludes).
+    /// With no globs every file is preprocessed.
+    pub fn new(command: &str, globs: &[String]) -> Result<Preprocessor, Box<dyn Error>> {
+        let mut filter: FileFilter = FileFilter::default();
+        for glob in globs {
+            filter.add_glob(glob, false)?;
+        }
+        return Ok(Preprocessor { command: command.to_string(), filter });
+    }
+
+    /// Whether the file at `relative`, a path relative to the searched directory (or just the
+    /// file's name), goes through the preprocessor.
+    pub fn applies_to(&self, relative: &Path) -> bool {
+        return self.filter.allows_file(relative);
+    }
+
+    /// Runs the command on `path` and returns its output as text. Output that isn't valid
+    /// UTF-8 is converted lossily rather than dropped.
+    pub fn run(&self, path: &Path) -> Result<String, PreprocessError> {
+        let error = |reason: String, stderr: &[u8]| PreprocessError {
+            path: path.to_path_buf(),
+            command: self.command.clone(),
+            reason,
+            stderr: String::from_utf8_lossy(stderr).into_owned(),
+        };
+        let stdin: File = File::open(path).map_err(|e| error(format!("couldn't open the file: {e}"), b""))?;
+        let output: Output = Command::new(&self.command)
+            .arg(path)
+            .stdin(stdin)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .map_err(|e| error(format!("couldn't be run: {e}"), b""))?;
+        if !output.status.success() {
+            return Err(error(format!("failed ({})", output.status), &output.stderr));
+        }
+        return Ok(String::from_utf8_lossy(&output.stdout).into_owned());
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    use crate::test_util::scratch_dir;
+
+    fn script(dir: &Path, name: &str, body: &str) -> String {
+        let path: PathBuf = dir.join(name);
+        fs::write(&path, format!("#!/bin/sh\n{body}\n")).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        return path.display().to_string();
+    }
+
+    #[test]
+    fn runs_the_command_and_reports_failures() {
+        let dir: PathBuf = scratch_dir("pre", &[("doc.pdf", "shouting\n")]);
+        let input: PathBuf = dir.join("doc.pdf");
+
+        let upper: Preprocessor = Preprocessor::new(&script(&dir, "upper", "tr a-z A-Z"), &[String::from("*.pdf")]).unwrap();
+        assert!(upper.applies_to(Path::new("docs/doc.pdf")));
+        assert!(!upper.applies_to(Path::new("notes.txt")));
+        assert_eq!("SHOUTING\n", upper.run(&input).unwrap());
+
+        let broken: Preprocessor = Preprocessor::new(&script(&dir, "broken", "echo \"bad file $1\" >&2; exit 3"), &[]).unwrap();
+        let error: PreprocessError = broken.run(&input).unwrap_err();
+        assert!(error.to_string().contains("failed (exit status: 3)"));
+        assert!(error.stderr.contains("bad file"));
+
+        let missing: Preprocessor = Preprocessor::new("/no/such/command", &[]).unwrap();
+        assert!(missing.run(&input).unwrap_err().reason.starts_with("couldn't be run"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
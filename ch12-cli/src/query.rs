@@ -0,0 +1,369 @@
+// A small boolean query language, evaluated one line at a time:
+//
+//   timeout AND (db OR cache) NOT retry
+//   "connection reset" OR refused
+//
+// Terms are matched the same way plain patterns are (literal, ignoring case or as a regex).
+// AND, OR and NOT must be written in capitals; lowercase "and" is just a word to search for.
+// Two terms next to each other are ANDed, NOT binds tightest and AND binds tighter than OR.
+// Double quotes make a phrase, which may contain spaces, parentheses and operator words.
+
+use std::error::Error;
+use std::fmt;
+
+use crate::matcher::{Match, MatchOptions, Matcher};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Query {
+    Term(String),
+    And(Box<Query>, Box<Query>),
+    Or(Box<Query>, Box<Query>),
+    Not(Box<Query>),
+}
+
+/// A syntax error in a query. `position` is the byte offset into the query and `column` the
+/// 1-based character column, which is what gets shown to the user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub position: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl ParseError {
+    fn new(input: &str, position: usize, message: &str) -> ParseError {
+        let column: usize = input[..position].chars().count() + 1;
+        return ParseError { position, column, message: message.to_string() };
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "query syntax error at column {}: {}", self.column, self.message);
+    }
+}
+
+impl Error for ParseError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    LParen,
+    RParen,
+    And,
+    Or,
+    Not,
+    Term(String),
+}
+
+fn tokenize(input: &str) -> Result<Vec<(usize, Token)>, ParseError> {
+    let mut tokens: Vec<(usize, Token)> = Vec::new();
+    let mut chars = input.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '(' {
+            tokens.push((start, Token::LParen));
+            chars.next();
+        } else if c == ')' {
+            tokens.push((start, Token::RParen));
+            chars.next();
+        } else if c == '"' {
+            chars.next();
+            let mut phrase: String = String::new();
+            let mut closed: bool = false;
+            while let Some((_, c)) = chars.next() {
+                match c {
+                    '"' => {
+                        closed = true;
+                        break;
+                    }
+                    '\\' => match chars.next() {
+                        Some((_, escaped)) => phrase.push(escaped),
+                        None => break,
+                    },
+                    _ => phrase.push(c),
+                }
+            }
+            if !closed {
+                return Err(ParseError::new(input, start, "unterminated phrase"));
+            }
+            tokens.push((start, Token::Term(phrase)));
+        } else {
+            let mut word: String = String::new();
+            while let Some(&(_, c)) = chars.peek() {
+                if c.is_whitespace() || c == '(' || c == ')' || c == '"' {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+            let token: Token = match word.as_str() {
+                "AND" => Token::And,
+                "OR" => Token::Or,
+                "NOT" => Token::Not,
+                _ => Token::Term(word),
+            };
+            tokens.push((start, token));
+        }
+    }
+    return Ok(tokens);
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        return self.tokens.get(self.pos).map(|(_, token)| token);
+    }
+
+    fn offset(&self) -> usize {
+        return match self.tokens.get(self.pos) {
+            Some((offset, _)) => *offset,
+            None => self.input.len(),
+        };
+    }
+
+    fn error(&self, message: &str) -> ParseError {
+        return ParseError::new(self.input, self.offset(), message);
+    }
+
+    fn or_expr(&mut self) -> Result<Query, ParseError> {
+        let mut left: Query = self.and_expr()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            let right: Query = self.and_expr()?;
+            left = Query::Or(Box::new(left), Box::new(right));
+        }
+        return Ok(left);
+    }
+
+    fn and_expr(&mut self) -> Result<Query, ParseError> {
+        let mut left: Query = self.unary()?;
+        loop {
+            match self.peek() {
+                Some(Token::And) => self.pos += 1,
+                // Juxtaposed terms are an implicit AND.
+                Some(Token::Term(_)) | Some(Token::Not) | Some(Token::LParen) => {}
+                _ => break,
+            }
+            let right: Query = self.unary()?;
+            left = Query::And(Box::new(left), Box::new(right));
+        }
+        return Ok(left);
+    }
+
+    fn unary(&mut self) -> Result<Query, ParseError> {
+        if self.peek() == Some(&Token::Not) {
+            self.pos += 1;
+            return Ok(Query::Not(Box::new(self.unary()?)));
+        }
+        return self.primary();
+    }
+
+    fn primary(&mut self) -> Result<Query, ParseError> {
+        let open: usize = self.offset();
+        return match self.peek().cloned() {
+            Some(Token::Term(term)) => {
+                self.pos += 1;
+                Ok(Query::Term(term))
+            }
+            Some(Token::LParen) => {
+                self.pos += 1;
+                let inner: Query = self.or_expr()?;
+                if self.peek() != Some(&Token::RParen) {
+                    return Err(ParseError::new(self.input, open, "unclosed parenthesis"));
+                }
+                self.pos += 1;
+                Ok(inner)
+            }
+            Some(Token::RParen) => Err(self.error("unexpected ')'")),
+            Some(Token::And) | Some(Token::Or) => Err(self.error("expected a term before operator")),
+            Some(Token::Not) => Err(self.error("expected a term")),
+            None => Err(self.error("expected a term at end of query")),
+        };
+    }
+}
+
+impl Query {
+    pub fn parse(input: &str) -> Result<Query, ParseError> {
+        let tokens: Vec<(usize, Token)> = tokenize(input)?;
+        if tokens.is_empty() {
+            return Err(ParseError::new(input, 0, "empty query"));
+        }
+        let mut parser: Parser = Parser { input, tokens, pos: 0 };
+        let query: Query = parser.or_expr()?;
+        if parser.pos < parser.tokens.len() {
+            return Err(parser.error("unexpected ')'"));
+        }
+        return Ok(query);
+    }
+
+    /// Every term in the query, left to right.
+    pub fn terms(&self) -> Vec<&str> {
+        let mut terms: Vec<&str> = Vec::new();
+        self.collect_terms(&mut terms);
+        return terms;
+    }
+
+    fn collect_terms<'q>(&'q self, terms: &mut Vec<&'q str>) {
+        match self {
+            Query::Term(term) => terms.push(term),
+            Query::And(a, b) | Query::Or(a, b) => {
+                a.collect_terms(terms);
+                b.collect_terms(terms);
+            }
+            Query::Not(inner) => inner.collect_terms(terms),
+        }
+    }
+
+    /// Whether some text could satisfy the query, given a test for whether a term might occur
+    /// in it. Used to pick candidate files from the index, so NOT never rules anything out.
+    pub fn may_match(&self, may_contain: &dyn Fn(&str) -> bool) -> bool {
+        return match self {
+            Query::Term(term) => may_contain(term),
+            Query::And(a, b) => a.may_match(may_contain) && b.may_match(may_contain),
+            Query::Or(a, b) => a.may_match(may_contain) || b.may_match(may_contain),
+            Query::Not(_) => true,
+        };
+    }
+}
+
+enum Node {
+    Term(Box<dyn Matcher>),
+    And(Box<Node>, Box<Node>),
+    Or(Box<Node>, Box<Node>),
+    Not(Box<Node>),
+}
+
+impl Node {
+    fn compile(query: &Query, options: &MatchOptions) -> Result<Node, Box<dyn Error>> {
+        return Ok(match query {
+            Query::Term(term) => Node::Term(options.build(term)?),
+            Query::And(a, b) => Node::And(Box::new(Node::compile(a, options)?), Box::new(Node::compile(b, options)?)),
+            Query::Or(a, b) => Node::Or(Box::new(Node::compile(a, options)?), Box::new(Node::compile(b, options)?)),
+            Query::Not(inner) => Node::Not(Box::new(Node::compile(inner, options)?)),
+        });
+    }
+
+    fn eval(&self, haystack: &str) -> bool {
+        return match self {
+            Node::Term(matcher) => matcher.is_match(haystack),
+            Node::And(a, b) => a.eval(haystack) && b.eval(haystack),
+            Node::Or(a, b) => a.eval(haystack) || b.eval(haystack),
+            Node::Not(inner) => !inner.eval(haystack),
+        };
+    }
+
+    // The first match at or after `at` of any term that isn't negated and helps the query
+    // match: a branch of an OR that isn't satisfied doesn't get highlighted.
+    fn first_positive(&self, haystack: &str, at: usize, negated: bool) -> Option<Match> {
+        // Under a NOT a branch helps by failing rather than by matching.
+        let helps = |node: &Node| node.eval(haystack) != negated;
+        return match self {
+            Node::Term(_) if negated => None,
+            Node::Term(matcher) => matcher.find_at(haystack, at),
+            Node::And(a, b) | Node::Or(a, b) => {
+                let left: Option<Match> = if helps(a) { a.first_positive(haystack, at, negated) } else { None };
+                let right: Option<Match> = if helps(b) { b.first_positive(haystack, at, negated) } else { None };
+                match (left, right) {
+                    (Some(l), Some(r)) => Some(if r.start < l.start { r } else { l }),
+                    (l, r) => l.or(r),
+                }
+            }
+            Node::Not(inner) => inner.first_positive(haystack, at, !negated),
+        };
+    }
+}
+
+/// Evaluates a boolean query against a whole line. When the line satisfies the query, the
+/// reported matches are the places where its non-negated terms occur.
+pub struct QueryMatcher {
+    root: Node,
+}
+
+impl QueryMatcher {
+    pub fn new(query: &Query, options: &MatchOptions) -> Result<QueryMatcher, Box<dyn Error>> {
+        return Ok(QueryMatcher { root: Node::compile(query, options)? });
+    }
+}
+
+impl Matcher for QueryMatcher {
+    fn find_at(&self, haystack: &str, at: usize) -> Option<Match> {
+        if !self.root.eval(haystack) {
+            return None;
+        }
+        if let Some(found) = self.root.first_positive(haystack, at, false) {
+            return Some(found);
+        }
+        // A query like `NOT retry` matches without highlighting anything.
+        if at == 0 {
+            return Some(Match::new(0, 0));
+        }
+        return None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn term(t: &str) -> Box<Query> {
+        return Box::new(Query::Term(t.to_string()));
+    }
+
+    #[test]
+    fn precedence_and_grouping() {
+        let query: Query = Query::parse("timeout AND (db OR cache) NOT retry").unwrap();
+        assert_eq!(
+            Query::And(
+                Box::new(Query::And(term("timeout"), Box::new(Query::Or(term("db"), term("cache"))))),
+                Box::new(Query::Not(term("retry")))
+            ),
+            query
+        );
+        assert_eq!(
+            Query::Or(term("a"), Box::new(Query::And(term("b"), term("c")))),
+            Query::parse("a OR b c").unwrap()
+        );
+    }
+
+    #[test]
+    fn phrases_keep_spaces_and_operators() {
+        assert_eq!(Query::Term("pick AND three".to_string()), Query::parse("\"pick AND three\"").unwrap());
+    }
+
+    #[test]
+    fn errors_report_position() {
+        let err: ParseError = Query::parse("db OR (cache").unwrap_err();
+        assert_eq!((7, "unclosed parenthesis"), (err.column, err.message.as_str()));
+        assert_eq!(9, Query::parse("timeout \"db").unwrap_err().column);
+        assert_eq!(4, Query::parse("db )").unwrap_err().column);
+        assert_eq!(9, Query::parse("db AND  ").unwrap_err().column);
+        assert_eq!(1, Query::parse("OR db").unwrap_err().column);
+    }
+
+    #[test]
+    fn evaluates_per_line_with_options() {
+        let options: MatchOptions = MatchOptions { ignore_case: true, ..MatchOptions::default() };
+        let query: Query = Query::parse("timeout AND (db OR cache) NOT retry").unwrap();
+        let matcher: QueryMatcher = QueryMatcher::new(&query, &options).unwrap();
+        assert!(matcher.is_match("DB Timeout after 5s"));
+        assert!(!matcher.is_match("db timeout, retry 1"));
+        assert!(!matcher.is_match("timeout only"));
+        assert_eq!(Some(Match::new(0, 2)), matcher.find("DB Timeout after 5s"));
+    }
+
+    #[test]
+    fn highlights_only_the_branch_that_matched() {
+        let query: Query = Query::parse("a OR (b AND c)").unwrap();
+        let matcher: QueryMatcher = QueryMatcher::new(&query, &MatchOptions::default()).unwrap();
+        // With -U the whole buffer is one record; `b` is there but its branch needs `c` too.
+        assert_eq!(vec![Match::new(0, 1)], matcher.find_all("a\nb\n"));
+        assert_eq!(vec![Match::new(0, 1), Match::new(2, 3)], matcher.find_all("b\nc\n"));
+        assert!(!matcher.is_match("b\n"));
+    }
+}
@@ -0,0 +1,302 @@
+// Relevance ranking for documentation search. Instead of every matching line in file order,
+// rank mode scores whole files (or paragraphs) against the query's words with Okapi BM25 and
+// prints the best few, each with the line that matches the query best.
+//
+// Text is split into lowercased runs of letters and digits, so ranking ignores case and
+// punctuation. Scoring needs each document's length and term counts, which means reading every
+// file; to make repeated queries fast those statistics are cached in `.minigrep.rank` at the
+// root of the searched directory and only recomputed for files whose mtime or size changed.
+// The cache is JSON:
+//
+//   {"version": 1, "files": {"PATH": {"mtime": [SECS, NANOS], "size": N,
+//                                     "paragraphs": [[FIRST_LINE, LENGTH, {"TERM": COUNT}]]}}}
+//
+// A cache that can't be read is simply rebuilt.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde_json::{Value, json};
+
+use crate::index::{self, FileStamp};
+use crate::walk;
+
+pub const CACHE_FILE_NAME: &str = ".minigrep.rank";
+
+const VERSION: u64 = 1;
+
+// The usual BM25 parameters: how quickly repeated terms stop adding to the score, and how much
+// long documents are penalized.
+const K1: f64 = 1.2;
+const B: f64 = 0.75;
+
+/// The lowercased words of `text`.
+pub fn tokenize(text: &str) -> Vec<String> {
+    return text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect();
+}
+
+/// What gets scored: whole files, or the paragraphs (runs of non-blank lines) in them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Unit {
+    #[default]
+    File,
+    Paragraph,
+}
+
+/// The statistics BM25 needs about one paragraph.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Paragraph {
+    first_line: usize,
+    length: usize,
+    terms: HashMap<String, u32>,
+}
+
+fn paragraphs(text: &str) -> Vec<Paragraph> {
+    let mut paragraphs: Vec<Paragraph> = Vec::new();
+    let mut current: Option<Paragraph> = None;
+    for (index, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            paragraphs.extend(current.take());
+            continue;
+        }
+        let paragraph: &mut Paragraph =
+            current.get_or_insert_with(|| Paragraph { first_line: index + 1, length: 0, terms: HashMap::new() });
+        for word in tokenize(line) {
+            paragraph.length += 1;
+            *paragraph.terms.entry(word).or_default() += 1;
+        }
+    }
+    paragraphs.extend(current);
+    return paragraphs;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CachedFile {
+    stamp: FileStamp,
+    paragraphs: Vec<Paragraph>,
+}
+
+/// Per-file document statistics for a directory, kept up to date by mtime and size.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct RankCache {
+    files: BTreeMap<String, CachedFile>,
+}
+
+/// One ranked document: a file, or the paragraph starting at `line`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ranked {
+    pub path: PathBuf,
+    pub line: usize,
+    pub score: f64,
+}
+
+impl RankCache {
+    pub fn cache_path(root: &Path) -> PathBuf {
+        return root.join(CACHE_FILE_NAME);
+    }
+
+    /// Loads the cache for `root`. A missing or unreadable cache gives an empty one.
+    pub fn open(root: &Path) -> RankCache {
+        return fs::read_to_string(RankCache::cache_path(root))
+            .ok()
+            .and_then(|text| serde_json::from_str::<Value>(&text).ok())
+            .and_then(|value| RankCache::decode(&value))
+            .unwrap_or_default();
+    }
+
+    pub fn save(&self, root: &Path) -> io::Result<()> {
+        return index::write_atomically(&RankCache::cache_path(root), self.encode().to_string().as_bytes());
+    }
+
+    /// Makes the cache cover exactly `files`, re-reading those that changed. Returns how many
+    /// files had to be read (or were dropped), so the caller knows whether to save.
+    pub fn update(&mut self, root: &Path, files: &[PathBuf]) -> io::Result<usize> {
+        let mut previous: BTreeMap<String, CachedFile> = std::mem::take(&mut self.files);
+        let mut changed: usize = 0;
+        for path in files {
+            let relative: String = path.strip_prefix(root).unwrap_or(path).to_string_lossy().into_owned();
+            let stamp: FileStamp = FileStamp::of(path)?;
+            if let Some(old) = previous.remove(&relative)
+                && old.stamp == stamp
+            {
+                self.files.insert(relative, old);
+                continue;
+            }
+            let contents: String = match walk::read_text(path, false) {
+                Ok(contents) => contents,
+                // Binary files have no words to rank.
+                Err(e) if e.kind() == io::ErrorKind::InvalidData => String::new(),
+                Err(e) => return Err(e),
+            };
+            changed += 1;
+            self.files.insert(relative, CachedFile { stamp, paragraphs: paragraphs(&contents) });
+        }
+        return Ok(changed + previous.len());
+    }
+
+    /// Scores every document containing at least one of `terms` and returns the `top` best,
+    /// highest score first.
+    pub fn rank(&self, root: &Path, terms: &[String], unit: Unit, top: usize) -> Vec<Ranked> {
+        // Collapse each file to one document when ranking files.
+        let mut documents: Vec<(&str, usize, usize, HashMap<&str, u32>)> = Vec::new();
+        for (path, file) in &self.files {
+            let mut whole: (usize, HashMap<&str, u32>) = (0, HashMap::new());
+            for paragraph in &file.paragraphs {
+                let counts: HashMap<&str, u32> = terms
+                    .iter()
+                    .filter_map(|term| paragraph.terms.get(term).map(|&count| (term.as_str(), count)))
+                    .collect();
+                if unit == Unit::Paragraph {
+                    documents.push((path, paragraph.first_line, paragraph.length, counts));
+                    continue;
+                }
+                whole.0 += paragraph.length;
+                for (term, count) in counts {
+                    *whole.1.entry(term).or_default() += count;
+                }
+            }
+            if unit == Unit::File {
+                documents.push((path, 1, whole.0, whole.1));
+            }
+        }
+        if documents.is_empty() {
+            return Vec::new();
+        }
+        let total: f64 = documents.len() as f64;
+        let average_length: f64 = (documents.iter().map(|d| d.2).sum::<usize>() as f64 / total).max(1.0);
+        let mut idf: HashMap<&str, f64> = HashMap::new();
+        for term in terms {
+            let containing: f64 = documents.iter().filter(|d| d.3.contains_key(term.as_str())).count() as f64;
+            idf.insert(term, ((total - containing + 0.5) / (containing + 0.5) + 1.0).ln());
+        }
+        let mut ranked: Vec<Ranked> = Vec::new();
+        for (path, line, length, counts) in &documents {
+            if counts.is_empty() {
+                continue;
+            }
+            let mut score: f64 = 0.0;
+            for (term, &count) in counts {
+                let tf: f64 = count as f64;
+                score += idf[term] * tf * (K1 + 1.0) / (tf + K1 * (1.0 - B + B * *length as f64 / average_length));
+            }
+            ranked.push(Ranked { path: root.join(path), line: *line, score });
+        }
+        ranked.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.path.cmp(&b.path)).then(a.line.cmp(&b.line)));
+        ranked.truncate(top);
+        return ranked;
+    }
+
+    fn encode(&self) -> Value {
+        let mut files: serde_json::Map<String, Value> = serde_json::Map::new();
+        for (path, file) in &self.files {
+            let paragraphs: Vec<Value> =
+                file.paragraphs.iter().map(|p| json!([p.first_line, p.length, p.terms])).collect();
+            files.insert(
+                path.clone(),
+                json!({"mtime": [file.stamp.mtime_secs, file.stamp.mtime_nanos], "size": file.stamp.size, "paragraphs": paragraphs}),
+            );
+        }
+        return json!({"version": VERSION, "files": files});
+    }
+
+    fn decode(value: &Value) -> Option<RankCache> {
+        if value["version"].as_u64()? != VERSION {
+            return None;
+        }
+        let mut cache: RankCache = RankCache::default();
+        for (path, file) in value["files"].as_object()? {
+            let mut paragraphs: Vec<Paragraph> = Vec::new();
+            for paragraph in file["paragraphs"].as_array()? {
+                let mut terms: HashMap<String, u32> = HashMap::new();
+                for (term, count) in paragraph[2].as_object()? {
+                    terms.insert(term.clone(), count.as_u64()? as u32);
+                }
+                // Line numbers count from 1; a 0 can only come from a damaged cache.
+                let first_line: usize = paragraph[0].as_u64().filter(|&line| line > 0)? as usize;
+                paragraphs.push(Paragraph {
+                    first_line,
+                    length: paragraph[1].as_u64()? as usize,
+                    terms,
+                });
+            }
+            let stamp: FileStamp = FileStamp {
+                mtime_secs: file["mtime"][0].as_u64()?,
+                mtime_nanos: file["mtime"][1].as_u64()? as u32,
+                size: file["size"].as_u64()?,
+            };
+            cache.files.insert(path.clone(), CachedFile { stamp, paragraphs });
+        }
+        return Some(cache);
+    }
+}
+
+/// The line of a document that matches `terms` best: the most distinct terms, then the most
+/// occurrences. `text` is the whole file and the document starts at `first_line`; a paragraph
+/// ends at the first blank line. Returns the line number and the line.
+pub fn snippet<'a>(text: &'a str, first_line: usize, unit: Unit, terms: &[String]) -> Option<(usize, &'a str)> {
+    let mut best: Option<((usize, usize), usize, &'a str)> = None;
+    for (index, line) in text.lines().enumerate().skip(first_line.saturating_sub(1)) {
+        if unit == Unit::Paragraph && line.trim().is_empty() {
+            break;
+        }
+        let words: Vec<String> = tokenize(line);
+        let distinct: usize = terms.iter().filter(|term| words.contains(term)).count();
+        let occurrences: usize = words.iter().filter(|word| terms.contains(word)).count();
+        if distinct > 0 && best.as_ref().is_none_or(|(score, _, _)| (distinct, occurrences) > *score) {
+            best = Some(((distinct, occurrences), index + 1, line));
+        }
+    }
+    return best.map(|(_, line_number, line)| (line_number, line));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache(files: &[(&str, &str)]) -> RankCache {
+        let mut cache: RankCache = RankCache::default();
+        for (path, text) in files {
+            cache.files.insert(path.to_string(), CachedFile { stamp: FileStamp::default(), paragraphs: paragraphs(text) });
+        }
+        return cache;
+    }
+
+    #[test]
+    fn tokenizes_words() {
+        assert_eq!(vec!["don", "t", "panic", "v2"], tokenize("Don't PANIC -- v2!"));
+    }
+
+    #[test]
+    fn ranks_files_and_paragraphs() {
+        let cache: RankCache = cache(&[
+            ("a.md", "install the tool\n\nconfigure the index\n"),
+            ("b.md", "index index index\nthe index is rebuilt on install\n"),
+            ("c.md", "nothing relevant here\n"),
+        ]);
+        let terms: Vec<String> = tokenize("index install");
+        let files: Vec<Ranked> = cache.rank(Path::new("docs"), &terms, Unit::File, 10);
+        let order: Vec<PathBuf> = files.iter().map(|r| r.path.clone()).collect();
+        assert_eq!(vec![PathBuf::from("docs/b.md"), PathBuf::from("docs/a.md")], order);
+        let paragraphs: Vec<Ranked> = cache.rank(Path::new("docs"), &terms, Unit::Paragraph, 2);
+        assert_eq!((PathBuf::from("docs/b.md"), 1), (paragraphs[0].path.clone(), paragraphs[0].line));
+        assert_eq!(2, paragraphs.len());
+    }
+
+    #[test]
+    fn cache_round_trips_and_snippets() {
+        let cache: RankCache = cache(&[("a.md", "One two\n\nthree two two\n")]);
+        assert_eq!(Some(cache.files.clone()), RankCache::decode(&cache.encode()).map(|c| c.files));
+        let terms: Vec<String> = tokenize("two three");
+        assert_eq!(Some((3, "three two two")), snippet("One two\n\nthree two two\n", 1, Unit::File, &terms));
+        assert_eq!(Some((1, "One two")), snippet("One two\n\nthree two two\n", 1, Unit::Paragraph, &terms));
+        let damaged: Value = json!({"version": VERSION, "files": {"a.md": {"mtime": [0, 0], "size": 0, "paragraphs": [[0, 2, {"two": 1}]]}}});
+        assert_eq!(None, RankCache::decode(&damaged));
+        assert_eq!(Some((1, "One two")), snippet("One two\n", 0, Unit::File, &terms));
+    }
+}
@@ -0,0 +1,157 @@
+// Splits text into the records that get searched one at a time. Normally a record is a line,
+// but it can also be everything between two NUL bytes (the output of `find -print0`), between
+// any other separator, or a whole paragraph of lines separated by blank lines.
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Terminator {
+    /// Lines ending in "\n" or "\r\n", like `str::lines`.
+    #[default]
+    Line,
+    /// Records ending in the given separator, for example "\0".
+    Separator(String),
+    /// Runs of non-blank lines. A paragraph's number is the line number of its first line.
+    Paragraph,
+}
+
+impl Terminator {
+    /// Whether record numbers are line numbers, so that line `n + i` of a record is line
+    /// `n + i` of the file.
+    pub fn numbers_lines(&self) -> bool {
+        return !matches!(self, Terminator::Separator(_));
+    }
+}
+
+/// Turns the escapes \0, \t, \n, \r and \\ in a separator given on the command line into the
+/// characters they stand for, since a NUL can't be passed as an argument.
+pub fn unescape(text: &str) -> String {
+    let mut result: String = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('0') => result.push('\0'),
+            Some('t') => result.push('\t'),
+            Some('n') => result.push('\n'),
+            Some('r') => result.push('\r'),
+            Some(other) => {
+                if other != '\\' {
+                    result.push('\\');
+                }
+                result.push(other);
+            }
+            None => result.push('\\'),
+        }
+    }
+    return result;
+}
+
+/// Iterates over `(number, record)` pairs, numbering from 1.
+pub struct Records<'a> {
+    text: &'a str,
+    terminator: Terminator,
+    pos: usize,
+    number: usize,
+}
+
+impl<'a> Records<'a> {
+    pub fn new(text: &'a str, terminator: &Terminator) -> Records<'a> {
+        return Records { text, terminator: terminator.clone(), pos: 0, number: 0 };
+    }
+
+    // The next line and the offset just past its terminator.
+    fn next_line(&self, from: usize) -> Option<(&'a str, usize)> {
+        if from >= self.text.len() {
+            return None;
+        }
+        let rest: &'a str = &self.text[from..];
+        let (line, next): (&'a str, usize) = match rest.find('\n') {
+            Some(newline) => (&rest[..newline], from + newline + 1),
+            None => (rest, self.text.len()),
+        };
+        return Some((line.strip_suffix('\r').unwrap_or(line), next));
+    }
+}
+
+impl<'a> Iterator for Records<'a> {
+    type Item = (usize, &'a str);
+
+    fn next(&mut self) -> Option<(usize, &'a str)> {
+        match &self.terminator {
+            Terminator::Line => {
+                let (line, next) = self.next_line(self.pos)?;
+                self.pos = next;
+                self.number += 1;
+                return Some((self.number, line));
+            }
+            Terminator::Separator(separator) => {
+                if self.pos >= self.text.len() || separator.is_empty() {
+                    return None;
+                }
+                let rest: &'a str = &self.text[self.pos..];
+                let record: &'a str = match rest.find(separator.as_str()) {
+                    Some(end) => {
+                        self.pos += end + separator.len();
+                        &rest[..end]
+                    }
+                    None => {
+                        self.pos = self.text.len();
+                        rest
+                    }
+                };
+                self.number += 1;
+                return Some((self.number, record));
+            }
+            Terminator::Paragraph => {
+                // Skip blank lines, then take lines until the next blank one.
+                loop {
+                    let (line, next) = self.next_line(self.pos)?;
+                    if !line.trim().is_empty() {
+                        break;
+                    }
+                    self.pos = next;
+                    self.number += 1;
+                }
+                let start: usize = self.pos;
+                let first_line: usize = self.number + 1;
+                let mut end: usize = start;
+                while let Some((line, next)) = self.next_line(self.pos) {
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    end = self.pos + line.len();
+                    self.pos = next;
+                    self.number += 1;
+                }
+                return Some((first_line, &self.text[start..end]));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_match_str_lines() {
+        let text: &str = "a\r\nb\n\nc\n";
+        let records: Vec<&str> = Records::new(text, &Terminator::Line).map(|(_, r)| r).collect();
+        assert_eq!(text.lines().collect::<Vec<&str>>(), records);
+    }
+
+    #[test]
+    fn nul_separated() {
+        let records: Vec<(usize, &str)> = Records::new("./a b\0./c\nd\0", &Terminator::Separator(unescape("\\0"))).collect();
+        assert_eq!(vec![(1, "./a b"), (2, "./c\nd")], records);
+    }
+
+    #[test]
+    fn paragraphs_start_at_their_first_line() {
+        let text: &str = "I'm nobody!\nAre you nobody, too?\n\n\nHow dreary\r\nto be somebody!\n";
+        let records: Vec<(usize, &str)> = Records::new(text, &Terminator::Paragraph).collect();
+        assert_eq!(vec![(1, "I'm nobody!\nAre you nobody, too?"), (5, "How dreary\r\nto be somebody!")], records);
+    }
+}
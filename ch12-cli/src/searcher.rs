@@ -0,0 +1,386 @@
+// The embeddable search API. A SearcherBuilder collects the options, builds the Matcher for
+// them and hands back a Searcher. Results come back either as a lazy iterator or through a
+// sink callback; nothing in here ever prints.
+
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+
+use crate::matcher::{AnyMatcher, Match, MatchOptions, Matcher};
+use crate::normalize::Normalization;
+use crate::query::{Query, QueryMatcher};
+use crate::records::{Records, Terminator};
+use crate::stats::Stats;
+
+/// One line that matched, with its 1-based line number and where the matches are in it. With a
+/// custom terminator `line` is the whole record and `line_number` its record number.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineMatch<'a> {
+    pub line_number: usize,
+    pub line: &'a str,
+    pub matches: Vec<Match>,
+}
+
+#[derive(Default)]
+pub struct SearcherBuilder {
+    patterns: Vec<String>,
+    options: MatchOptions,
+    boolean: bool,
+    multiline: bool,
+    terminator: Terminator,
+    matcher: Option<Box<dyn Matcher>>,
+}
+
+impl SearcherBuilder {
+    pub fn new() -> SearcherBuilder {
+        return SearcherBuilder::default();
+    }
+
+    /// Adds a pattern to search for. A line matches if any of the patterns match.
+    pub fn pattern(mut self, pattern: &str) -> SearcherBuilder {
+        self.patterns.push(pattern.to_string());
+        return self;
+    }
+
+    pub fn patterns<I, S>(mut self, patterns: I) -> SearcherBuilder
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        for pattern in patterns {
+            self.patterns.push(pattern.as_ref().to_string());
+        }
+        return self;
+    }
+
+    pub fn ignore_case(mut self, yes: bool) -> SearcherBuilder {
+        self.options.ignore_case = yes;
+        return self;
+    }
+
+    /// Ignores case for patterns without uppercase letters and matches the others exactly.
+    /// Overrides `ignore_case`.
+    pub fn smart_case(mut self, yes: bool) -> SearcherBuilder {
+        self.options.smart_case = yes;
+        return self;
+    }
+
+    /// Treats the patterns as regular expressions instead of literal text.
+    pub fn regex(mut self, yes: bool) -> SearcherBuilder {
+        self.options.regex = yes;
+        return self;
+    }
+
+    /// Normalizes the patterns and the text before matching, so that canonically equivalent
+    /// text matches (and, when folding, accents are ignored).
+    pub fn normalization(mut self, normalization: Normalization) -> SearcherBuilder {
+        self.options.normalization = normalization;
+        return self;
+    }
+
+    /// Parses each pattern as a boolean query (see the query module). The matching options
+    /// apply to every term in the query.
+    pub fn boolean(mut self, yes: bool) -> SearcherBuilder {
+        self.boolean = yes;
+        return self;
+    }
+
+    /// Matches against the whole text instead of line by line, so a match may span lines.
+    pub fn multiline(mut self, yes: bool) -> SearcherBuilder {
+        self.multiline = yes;
+        return self;
+    }
+
+    /// Sets what separates the records that are searched one at a time. Ignored in multiline
+    /// mode, which always works on lines.
+    pub fn terminator(mut self, terminator: Terminator) -> SearcherBuilder {
+        self.terminator = terminator;
+        return self;
+    }
+
+    /// Uses a caller supplied matcher. The patterns and matching options are then ignored.
+    pub fn matcher(mut self, matcher: Box<dyn Matcher>) -> SearcherBuilder {
+        self.matcher = Some(matcher);
+        return self;
+    }
+
+    pub fn build(self) -> Result<Searcher, Box<dyn Error>> {
+        let multiline: bool = self.multiline;
+        let terminator: Terminator = self.terminator;
+        if let Some(matcher) = self.matcher {
+            return Ok(Searcher { matcher, multiline, terminator });
+        }
+        if self.patterns.is_empty() {
+            return Err("No pattern given!".into());
+        }
+        let mut matchers: Vec<Box<dyn Matcher>> = Vec::new();
+        for pattern in &self.patterns {
+            if self.boolean {
+                let query: Query = Query::parse(pattern)?;
+                matchers.push(Box::new(QueryMatcher::new(&query, &self.options)?));
+            } else {
+                matchers.push(self.options.build(pattern)?);
+            }
+        }
+        let matcher: Box<dyn Matcher> = if matchers.len() == 1 {
+            matchers.remove(0)
+        } else {
+            Box::new(AnyMatcher::new(matchers))
+        };
+        return Ok(Searcher { matcher, multiline, terminator });
+    }
+}
+
+pub struct Searcher {
+    matcher: Box<dyn Matcher>,
+    multiline: bool,
+    terminator: Terminator,
+}
+
+impl Searcher {
+    /// A line-by-line searcher for `matcher`. Unlike the builder this can't fail, since there's
+    /// no pattern left to parse.
+    pub fn new(matcher: Box<dyn Matcher>) -> Searcher {
+        return Searcher { matcher, multiline: false, terminator: Terminator::default() };
+    }
+
+    pub fn matcher(&self) -> &dyn Matcher {
+        return self.matcher.as_ref();
+    }
+
+    /// Lazily yields each matching line of `contents`. In multiline mode each item is instead
+    /// the span of whole lines a match covers, numbered from its first line.
+    pub fn search<'s, 'a>(&'s self, contents: &'a str) -> SearchIter<'s, 'a> {
+        return SearchIter {
+            matcher: self.matcher.as_ref(),
+            contents,
+            records: Records::new(contents, &self.terminator),
+            multiline: self.multiline,
+            pos: 0,
+            line_number: 1,
+            lines_scanned: 0,
+        };
+    }
+
+    /// Calls `sink` with each matching line of `contents`. The sink returns false to stop early.
+    /// Returns how much work the search did, counting `contents` as one file.
+    pub fn search_with<'a, F>(&self, contents: &'a str, mut sink: F) -> Stats
+    where
+        F: FnMut(&LineMatch<'a>) -> bool,
+    {
+        let start: Instant = Instant::now();
+        let mut stats: Stats = Stats { files_searched: 1, bytes_read: contents.len() as u64, ..Stats::default() };
+        let mut iter: SearchIter = self.search(contents);
+        for line_match in iter.by_ref() {
+            stats.matched_lines += 1;
+            stats.matches += line_match.matches.len() as u64;
+            if !sink(&line_match) {
+                break;
+            }
+        }
+        stats.lines_scanned = iter.lines_scanned() as u64;
+        stats.files_matched = (stats.matched_lines > 0) as usize;
+        stats.elapsed = start.elapsed();
+        return stats;
+    }
+
+    /// Reads the file at `path` and calls `sink` with each matching line.
+    pub fn search_path<F>(&self, path: &Path, sink: F) -> Result<Stats, Box<dyn Error>>
+    where
+        F: FnMut(&LineMatch) -> bool,
+    {
+        let start: Instant = Instant::now();
+        let contents: String = fs::read_to_string(path)?;
+        let mut stats: Stats = self.search_with(&contents, sink);
+        stats.elapsed = start.elapsed();
+        return Ok(stats);
+    }
+}
+
+pub struct SearchIter<'s, 'a> {
+    matcher: &'s dyn Matcher,
+    contents: &'a str,
+    records: Records<'a>,
+    multiline: bool,
+    // Multiline mode only: where to search from next, and the line number at that offset.
+    pos: usize,
+    line_number: usize,
+    lines_scanned: usize,
+}
+
+impl<'a> SearchIter<'_, 'a> {
+    /// How many lines the iterator has looked at so far.
+    pub fn lines_scanned(&self) -> usize {
+        if !self.multiline {
+            return self.lines_scanned;
+        }
+        if self.pos > self.contents.len() || self.matcher.find_at(self.contents, self.pos).is_none() {
+            return self.contents.lines().count();
+        }
+        return self.line_number - 1;
+    }
+
+    fn next_span(&mut self) -> Option<LineMatch<'a>> {
+        let contents: &'a str = self.contents;
+        let first: Match = self.matcher.find_at(contents, self.pos)?;
+        let start: usize = match contents[..first.start].rfind('\n') {
+            Some(newline) => newline + 1,
+            None => 0,
+        };
+        let mut end: usize = line_end(contents, first);
+        let mut matches: Vec<Match> = vec![first];
+        let mut at: usize = next_search_offset(contents, first);
+        // Later matches that begin inside the span's lines belong to the same span.
+        while at <= end {
+            match self.matcher.find_at(contents, at) {
+                Some(found) if found.start <= end => {
+                    end = end.max(line_end(contents, found));
+                    at = next_search_offset(contents, found);
+                    matches.push(found);
+                }
+                _ => break,
+            }
+        }
+        self.line_number += contents[self.pos.min(start)..start].matches('\n').count();
+        let line_number: usize = self.line_number;
+        let mut line: &'a str = &contents[start..end];
+        if line.ends_with('\r') {
+            line = &line[..line.len() - 1];
+        }
+        for m in matches.iter_mut() {
+            m.start -= start;
+            m.end = (m.end - start).min(line.len());
+        }
+        self.line_number += contents[start..(end + 1).min(contents.len())].matches('\n').count();
+        self.pos = (end + 1).min(contents.len() + 1);
+        return Some(LineMatch { line_number, line, matches });
+    }
+}
+
+// The offset of the newline ending the last line a match touches (or the end of the text).
+fn line_end(contents: &str, found: Match) -> usize {
+    // A match that ends right after a newline doesn't reach into the next line.
+    let last: usize = if found.end > found.start && contents[..found.end].ends_with('\n') {
+        found.end - 1
+    } else {
+        found.end
+    };
+    return match contents[last..].find('\n') {
+        Some(newline) => last + newline,
+        None => contents.len(),
+    };
+}
+
+fn next_search_offset(contents: &str, found: Match) -> usize {
+    if found.end > found.start {
+        return found.end;
+    }
+    return crate::matcher::next_char_boundary(contents, found.end);
+}
+
+impl<'a> Iterator for SearchIter<'_, 'a> {
+    type Item = LineMatch<'a>;
+
+    fn next(&mut self) -> Option<LineMatch<'a>> {
+        if self.multiline {
+            if self.pos > self.contents.len() {
+                return None;
+            }
+            return self.next_span();
+        }
+        for (line_number, line) in self.records.by_ref() {
+            self.lines_scanned += 1;
+            let matches: Vec<Match> = self.matcher.find_all(line);
+            if !matches.is_empty() {
+                return Some(LineMatch { line_number, line, matches });
+            }
+        }
+        return None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matcher::RegexMatcher;
+
+    const CONTENTS: &str = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Trust me.";
+
+    #[test]
+    fn iterator_reports_line_numbers() {
+        let searcher: Searcher = SearcherBuilder::new().pattern("rust").ignore_case(true).build().unwrap();
+        let numbers: Vec<usize> = searcher.search(CONTENTS).map(|m| m.line_number).collect();
+        assert_eq!(vec![1, 4], numbers);
+    }
+
+    #[test]
+    fn sink_can_stop_early() {
+        let searcher: Searcher = SearcherBuilder::new().patterns(["Pick", "safe"]).build().unwrap();
+        let mut seen: Vec<&str> = Vec::new();
+        searcher.search_with(CONTENTS, |m| {
+            seen.push(m.line);
+            return false;
+        });
+        assert_eq!(vec!["safe, fast, productive."], seen);
+    }
+
+    #[test]
+    fn custom_matcher() {
+        let searcher: Searcher = SearcherBuilder::new()
+            .matcher(Box::new(RegexMatcher::new(r"^\w+ \w+\.$", false).unwrap()))
+            .build()
+            .unwrap();
+        let lines: Vec<&str> = searcher.search(CONTENTS).map(|m| m.line).collect();
+        assert_eq!(vec!["Pick three.", "Trust me."], lines);
+    }
+
+    #[test]
+    fn multiline_spans_lines() {
+        let contents: &str = "fn main(\n    x: u32,\n) {}\nfn other() {}\nlet y = main(\n1);";
+        let searcher: Searcher = SearcherBuilder::new().pattern(r"main\(\s*\w").regex(true).multiline(true).build().unwrap();
+        let found: Vec<LineMatch> = searcher.search(contents).collect();
+        assert_eq!(2, found.len());
+        assert_eq!((1, "fn main(\n    x: u32,"), (found[0].line_number, found[0].line));
+        assert_eq!((5, "let y = main(\n1);"), (found[1].line_number, found[1].line));
+        assert_eq!(Match::new(8, 15), found[1].matches[0]);
+    }
+
+    #[test]
+    fn multiline_merges_matches_on_the_same_lines() {
+        let searcher: Searcher = SearcherBuilder::new().pattern("a\nb").multiline(true).build().unwrap();
+        let found: Vec<LineMatch> = searcher.search("x\na\nb a\nb\nc").collect();
+        assert_eq!(1, found.len());
+        assert_eq!((2, "a\nb a\nb"), (found[0].line_number, found[0].line));
+        assert_eq!(2, found[0].matches.len());
+    }
+
+    #[test]
+    fn search_with_counts_work() {
+        let searcher: Searcher = SearcherBuilder::new().pattern("t").build().unwrap();
+        let stats: Stats = searcher.search_with(CONTENTS, |_| true);
+        assert_eq!((1, 1, 4, 4, 5), (stats.files_searched, stats.files_matched, stats.lines_scanned, stats.matched_lines, stats.matches));
+        assert_eq!(CONTENTS.len() as u64, stats.bytes_read);
+
+        let stats: Stats = searcher.search_with(CONTENTS, |_| false);
+        assert_eq!((1, 1), (stats.lines_scanned, stats.matched_lines));
+    }
+
+    #[test]
+    fn paragraph_records() {
+        let searcher: Searcher = SearcherBuilder::new().pattern("frog").terminator(Terminator::Paragraph).build().unwrap();
+        let found: Vec<LineMatch> = searcher.search(include_str!("../poem.txt")).collect();
+        assert_eq!(1, found.len());
+        assert_eq!(6, found[0].line_number);
+        assert!(found[0].line.starts_with("How dreary to be somebody!\nHow public"));
+    }
+
+    #[test]
+    fn no_pattern_is_an_error() {
+        assert!(SearcherBuilder::new().build().is_err());
+    }
+}
@@ -0,0 +1,619 @@
+// `minigrep serve [DIR]`: a long-running search server for editor plugins and scripts that
+// would otherwise start a process and re-read the same files for every search. It listens on a
+// localhost TCP port or a Unix socket (`--listen unix:PATH`) and speaks JSON-RPC 2.0, one
+// message per line, in both directions. A search looks like
+//
+//   {"jsonrpc": "2.0", "id": 1, "method": "search",
+//    "params": {"query": "Config::new", "paths": ["src"],
+//               "options": {"ignore_case": false, "smart_case": false, "regex": false,
+//                           "boolean": false, "globs": ["*.rs"], "max_count": 100}}}
+//
+// Matches and unreadable files are streamed back as notifications carrying the request's id,
+// and the search ends with the response:
+//
+//   {"jsonrpc": "2.0", "method": "match",
+//    "params": {"id": 1, "path": "src/lib.rs", "line_number": 3, "line": "...", "submatches": [[4, 15]]}}
+//   {"jsonrpc": "2.0", "method": "file_error", "params": {"id": 1, "path": "src/gone.rs", "message": "..."}}
+//   {"jsonrpc": "2.0", "id": 1, "result": {"cancelled": false, "cache_hits": 2, "stats": {...}}}
+//
+// A request that fails gets `{"jsonrpc": "2.0", "id": 1, "error": {"code": -32602, "message":
+// "..."}}` instead, with the standard JSON-RPC codes. Every request runs on its own thread, so a
+// connection can have several in flight, and the notification
+// `{"jsonrpc": "2.0", "method": "cancel", "params": {"id": 1}}` stops request 1 at the next
+// matching line or file.
+//
+// Only DIR (the current directory by default) is served: `paths` are relative to it, and a
+// path that leads outside it is refused.
+//
+// Files are kept in a cache shared by all connections, keyed by path and checked against the
+// file's mtime and size on every use, so edits are always seen. The least recently used files
+// are dropped once the cache holds more than its byte budget.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Instant, SystemTime};
+
+use serde_json::{Value, json};
+
+use crate::glob::FileFilter;
+use crate::searcher::{Searcher, SearcherBuilder};
+use crate::stats::Stats;
+use crate::walk;
+
+pub const DEFAULT_LISTEN: &str = "127.0.0.1:7878";
+
+// JSON-RPC 2.0 error codes.
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// In the range JSON-RPC leaves to servers: a request with the same id is still running.
+const REQUEST_RUNNING: i64 = -32000;
+
+const DEFAULT_CACHE_BYTES: usize = 256 * 1024 * 1024;
+
+struct CachedFile {
+    modified: SystemTime,
+    len: u64,
+    contents: Arc<String>,
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct CacheState {
+    files: HashMap<PathBuf, CachedFile>,
+    bytes: usize,
+    tick: u64,
+}
+
+/// Recently read files, revalidated by mtime and size.
+pub struct FileCache {
+    capacity: usize,
+    state: Mutex<CacheState>,
+}
+
+impl FileCache {
+    /// A cache holding up to `capacity` bytes of file contents.
+    pub fn new(capacity: usize) -> FileCache {
+        return FileCache { capacity, state: Mutex::new(CacheState::default()) };
+    }
+
+    /// The contents of `path`, and whether they came from the cache.
+    pub fn read(&self, path: &Path) -> io::Result<(Arc<String>, bool)> {
+        let metadata: fs::Metadata = fs::metadata(path)?;
+        let modified: SystemTime = metadata.modified()?;
+        {
+            let mut state = self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            state.tick += 1;
+            let tick: u64 = state.tick;
+            if let Some(cached) = state.files.get_mut(path)
+                && cached.modified == modified
+                && cached.len == metadata.len()
+            {
+                cached.last_used = tick;
+                return Ok((Arc::clone(&cached.contents), true));
+            }
+        }
+        // Read without holding the lock so other requests aren't held up by a slow disk.
+        let contents: Arc<String> = Arc::new(walk::read_text(path, false)?);
+        if contents.len() <= self.capacity {
+            let mut state = self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            let tick: u64 = state.tick;
+            let cached: CachedFile = CachedFile { modified, len: metadata.len(), contents: Arc::clone(&contents), last_used: tick };
+            state.bytes += contents.len();
+            if let Some(old) = state.files.insert(path.to_path_buf(), cached) {
+                state.bytes -= old.contents.len();
+            }
+            while state.bytes > self.capacity {
+                let oldest: PathBuf = match state.files.iter().min_by_key(|(_, file)| file.last_used) {
+                    Some((path, _)) => path.clone(),
+                    None => break,
+                };
+                let removed: CachedFile = state.files.remove(&oldest).unwrap();
+                state.bytes -= removed.contents.len();
+            }
+        }
+        return Ok((contents, false));
+    }
+}
+
+struct SearchRequest {
+    id: Value,
+    query: String,
+    paths: Vec<PathBuf>,
+    ignore_case: bool,
+    smart_case: bool,
+    regex: bool,
+    boolean: bool,
+    globs: Vec<String>,
+    max_count: Option<u64>,
+}
+
+enum Incoming {
+    Search(SearchRequest),
+    /// The id of the request to cancel, and the cancel's own id if it was sent as a request.
+    Cancel(Value, Value),
+}
+
+fn response(id: &Value, result: Value) -> Value {
+    return json!({"jsonrpc": "2.0", "id": id, "result": result});
+}
+
+fn error_response(id: &Value, code: i64, message: &str) -> Value {
+    return json!({"jsonrpc": "2.0", "id": id, "error": {"code": code, "message": message}});
+}
+
+fn notification(method: &str, params: Value) -> Value {
+    return json!({"jsonrpc": "2.0", "method": method, "params": params});
+}
+
+// A request, or the error response to send back if it isn't one.
+fn parse_request(line: &str) -> Result<Incoming, Value> {
+    let value: Value = serde_json::from_str(line).map_err(|e| error_response(&Value::Null, PARSE_ERROR, &format!("invalid JSON: {e}")))?;
+    let id: Value = value.get("id").cloned().unwrap_or(Value::Null);
+    let invalid = |code: i64, message: &str| error_response(&id, code, message);
+    if value["jsonrpc"] != "2.0" {
+        return Err(invalid(INVALID_REQUEST, "`jsonrpc` must be \"2.0\""));
+    }
+    let method: &str = value["method"].as_str().ok_or_else(|| invalid(INVALID_REQUEST, "request has no `method`"))?;
+    let params: &Value = &value["params"];
+    match method {
+        "search" => {}
+        "cancel" => {
+            let target: Value = params.get("id").cloned().ok_or_else(|| invalid(INVALID_PARAMS, "cancel needs the `id` of a search"))?;
+            return Ok(Incoming::Cancel(target, id));
+        }
+        _ => return Err(invalid(METHOD_NOT_FOUND, &format!("no method `{method}`"))),
+    }
+    if id.is_null() {
+        return Err(invalid(INVALID_REQUEST, "search needs an `id`"));
+    }
+    let query: String = params["query"].as_str().ok_or_else(|| invalid(INVALID_PARAMS, "search has no `query` string"))?.to_string();
+    let paths: Vec<PathBuf> = match params.get("paths") {
+        None => vec![PathBuf::from(".")],
+        Some(paths) => paths
+            .as_array()
+            .and_then(|paths| paths.iter().map(|p| p.as_str().map(PathBuf::from)).collect())
+            .ok_or_else(|| invalid(INVALID_PARAMS, "`paths` must be an array of strings"))?,
+    };
+    let options: &Value = &params["options"];
+    let flag = |name: &str| options[name].as_bool().unwrap_or(false);
+    let globs: Vec<String> = match options.get("globs") {
+        None => Vec::new(),
+        Some(globs) => globs
+            .as_array()
+            .and_then(|globs| globs.iter().map(|g| g.as_str().map(String::from)).collect())
+            .ok_or_else(|| invalid(INVALID_PARAMS, "`globs` must be an array of strings"))?,
+    };
+    return Ok(Incoming::Search(SearchRequest {
+        id,
+        query,
+        paths,
+        ignore_case: flag("ignore_case"),
+        smart_case: flag("smart_case"),
+        regex: flag("regex"),
+        boolean: flag("boolean"),
+        globs,
+        max_count: options["max_count"].as_u64(),
+    }));
+}
+
+fn stats_json(stats: &Stats) -> Value {
+    return json!({
+        "files_searched": stats.files_searched,
+        "files_matched": stats.files_matched,
+        "bytes_read": stats.bytes_read,
+        "lines_scanned": stats.lines_scanned,
+        "matched_lines": stats.matched_lines,
+        "matches": stats.matches,
+        "elapsed_ms": stats.elapsed.as_secs_f64() * 1000.0,
+    });
+}
+
+// `path` from a request, resolved against the served root, which has to contain it.
+fn resolve(root: &Path, path: &Path) -> Result<PathBuf, String> {
+    let joined: PathBuf = root.join(path);
+    let real: PathBuf = joined.canonicalize().map_err(|e| format!("{}: {e}", path.display()))?;
+    if !real.starts_with(root) {
+        return Err(format!("{} is outside the served directory", path.display()));
+    }
+    return Ok(joined);
+}
+
+// Runs one request against the files under `root` (already canonical), handing each message to
+// `send`. `send` returns false once the client is gone, which stops the search like a cancel
+// does.
+fn run_search(request: &SearchRequest, root: &Path, cache: &FileCache, cancel: &AtomicBool, send: &mut dyn FnMut(Value) -> bool) {
+    let start: Instant = Instant::now();
+    let id: &Value = &request.id;
+    let roots: Vec<PathBuf> = match request.paths.iter().map(|path| resolve(root, path)).collect() {
+        Ok(roots) => roots,
+        Err(message) => {
+            send(error_response(id, INVALID_PARAMS, &message));
+            return;
+        }
+    };
+    let setup = || -> Result<(Searcher, FileFilter), Box<dyn Error>> {
+        let searcher: Searcher = SearcherBuilder::new()
+            .pattern(&request.query)
+            .ignore_case(request.ignore_case)
+            .smart_case(request.smart_case)
+            .regex(request.regex)
+            .boolean(request.boolean)
+            .build()?;
+        let mut filter: FileFilter = FileFilter::default();
+        for glob in &request.globs {
+            filter.add_glob(glob, false)?;
+        }
+        return Ok((searcher, filter));
+    };
+    let (searcher, filter): (Searcher, FileFilter) = match setup() {
+        Ok(built) => built,
+        Err(e) => {
+            send(error_response(id, INVALID_PARAMS, &e.to_string()));
+            return;
+        }
+    };
+    let mut stats: Stats = Stats::default();
+    let mut cache_hits: usize = 0;
+    let mut matched: u64 = 0;
+    let mut stopped: bool = false;
+    'paths: for search_root in &roots {
+        let files: Vec<PathBuf> = match walk::walk_filtered(search_root, &filter) {
+            Ok(files) => files,
+            Err(e) => {
+                let path: &Path = search_root.strip_prefix(root).unwrap_or(search_root);
+                stopped = !send(notification("file_error", json!({"id": id, "path": path, "message": e.to_string()})));
+                if stopped {
+                    break;
+                }
+                continue;
+            }
+        };
+        for path in files {
+            if cancel.load(Ordering::SeqCst) {
+                break 'paths;
+            }
+            let contents: Arc<String> = match cache.read(&path) {
+                Ok((contents, hit)) => {
+                    cache_hits += hit as usize;
+                    contents
+                }
+                // Binary files are skipped, as in a directory search.
+                Err(e) if e.kind() == io::ErrorKind::InvalidData => continue,
+                Err(e) => {
+                    let path: &Path = path.strip_prefix(root).unwrap_or(&path);
+                    stopped = !send(notification("file_error", json!({"id": id, "path": path, "message": e.to_string()})));
+                    if stopped {
+                        break 'paths;
+                    }
+                    continue;
+                }
+            };
+            let reached_max = |matched: u64| request.max_count.is_some_and(|max| matched >= max);
+            stats += searcher.search_with(&contents, |line_match| {
+                if cancel.load(Ordering::SeqCst) {
+                    return false;
+                }
+                let submatches: Vec<[usize; 2]> = line_match.matches.iter().map(|m| [m.start, m.end]).collect();
+                let path: &Path = path.strip_prefix(root).unwrap_or(&path);
+                stopped = !send(notification(
+                    "match",
+                    json!({
+                        "id": id,
+                        "path": path,
+                        "line_number": line_match.line_number,
+                        "line": line_match.line,
+                        "submatches": submatches,
+                    }),
+                ));
+                matched += 1;
+                return !stopped && !reached_max(matched);
+            });
+            if stopped || reached_max(matched) {
+                break 'paths;
+            }
+        }
+    }
+    if stopped {
+        return;
+    }
+    stats.elapsed = start.elapsed();
+    send(response(
+        id,
+        json!({
+            "cancelled": cancel.load(Ordering::SeqCst),
+            "cache_hits": cache_hits,
+            "stats": stats_json(&stats),
+        }),
+    ));
+}
+
+type Writer = Arc<Mutex<Box<dyn Write + Send>>>;
+
+// Whether `event` is the last message a request gets: its response, which unlike the
+// notifications before it has no method.
+fn is_last_event(event: &Value) -> bool {
+    return event.get("method").is_none();
+}
+
+fn send_line(writer: &Writer, event: &Value) -> bool {
+    let mut writer = writer.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    return writeln!(writer, "{event}").and_then(|_| writer.flush()).is_ok();
+}
+
+// Reads requests from one client until it disconnects, starting a thread for each search.
+fn handle_connection(reader: Box<dyn Read + Send>, writer: Box<dyn Write + Send>, root: Arc<PathBuf>, cache: Arc<FileCache>) {
+    let writer: Writer = Arc::new(Mutex::new(writer));
+    let active: Arc<Mutex<HashMap<String, Arc<AtomicBool>>>> = Arc::new(Mutex::new(HashMap::new()));
+    for line in BufReader::new(reader).lines() {
+        let line: String = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let request: SearchRequest = match parse_request(&line) {
+            Ok(Incoming::Search(request)) => request,
+            Ok(Incoming::Cancel(target, id)) => {
+                let running: Option<Arc<AtomicBool>> = active.lock().unwrap().get(&target.to_string()).cloned();
+                if let Some(cancel) = &running {
+                    cancel.store(true, Ordering::SeqCst);
+                }
+                // A cancel sent as a notification gets no reply.
+                if !id.is_null() {
+                    send_line(&writer, &response(&id, json!({"cancelled": running.is_some()})));
+                }
+                continue;
+            }
+            Err(error) => {
+                send_line(&writer, &error);
+                continue;
+            }
+        };
+        let key: String = request.id.to_string();
+        let cancel: Arc<AtomicBool> = Arc::new(AtomicBool::new(false));
+        {
+            let mut active = active.lock().unwrap();
+            if active.contains_key(&key) {
+                send_line(&writer, &error_response(&request.id, REQUEST_RUNNING, "a request with this id is still running"));
+                continue;
+            }
+            active.insert(key.clone(), Arc::clone(&cancel));
+        }
+        let (writer, active, root, cache) = (Arc::clone(&writer), Arc::clone(&active), Arc::clone(&root), Arc::clone(&cache));
+        thread::spawn(move || {
+            // The id is freed before the last event goes out, since a client may reuse it as
+            // soon as it sees that event.
+            let mut freed: bool = false;
+            run_search(&request, &root, &cache, &cancel, &mut |event: Value| {
+                if is_last_event(&event) {
+                    active.lock().unwrap().remove(&key);
+                    freed = true;
+                }
+                return send_line(&writer, &event);
+            });
+            if !freed {
+                active.lock().unwrap().remove(&key);
+            }
+        });
+    }
+    // The client is gone, so nobody is waiting for the searches it started.
+    for cancel in active.lock().unwrap().values() {
+        cancel.store(true, Ordering::SeqCst);
+    }
+}
+
+pub enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(std::os::unix::net::UnixListener, PathBuf),
+}
+
+impl Listener {
+    /// Binds `address`: `HOST:PORT` for TCP, which must be a loopback address, or `unix:PATH`
+    /// for a Unix socket. A stale socket file left by an earlier server is replaced, and the
+    /// socket file is removed again when the listener is dropped.
+    pub fn bind(address: &str) -> Result<Listener, Box<dyn Error>> {
+        if let Some(path) = address.strip_prefix("unix:") {
+            return Listener::bind_unix(Path::new(path));
+        }
+        let addresses: Vec<SocketAddr> = address.to_socket_addrs()?.collect();
+        if addresses.is_empty() || addresses.iter().any(|a| !a.ip().is_loopback()) {
+            return Err(format!("refusing to listen on {address}: serve only listens on localhost").into());
+        }
+        return Ok(Listener::Tcp(TcpListener::bind(&addresses[..])?));
+    }
+
+    #[cfg(unix)]
+    fn bind_unix(path: &Path) -> Result<Listener, Box<dyn Error>> {
+        use std::os::unix::fs::FileTypeExt;
+        use std::os::unix::net::{UnixListener, UnixStream};
+        if let Ok(metadata) = fs::symlink_metadata(path)
+            && metadata.file_type().is_socket()
+            && UnixStream::connect(path).is_err()
+        {
+            fs::remove_file(path)?;
+        }
+        return Ok(Listener::Unix(UnixListener::bind(path)?, path.to_path_buf()));
+    }
+
+    #[cfg(not(unix))]
+    fn bind_unix(_path: &Path) -> Result<Listener, Box<dyn Error>> {
+        return Err("Unix sockets aren't supported on this platform".into());
+    }
+
+    /// The address clients should connect to.
+    pub fn address(&self) -> String {
+        return match self {
+            Listener::Tcp(listener) => listener.local_addr().map(|a| a.to_string()).unwrap_or_default(),
+            #[cfg(unix)]
+            Listener::Unix(_, path) => format!("unix:{}", path.display()),
+        };
+    }
+
+    /// The Unix socket file, if the listener has one.
+    pub fn socket_path(&self) -> Option<&Path> {
+        return match self {
+            Listener::Tcp(_) => None,
+            #[cfg(unix)]
+            Listener::Unix(_, path) => Some(path),
+        };
+    }
+
+    /// Accepts clients forever, each on its own thread, sharing one file cache. Searches are
+    /// limited to the files under `root`.
+    pub fn serve(&self, root: &Path) -> Result<(), Box<dyn Error>> {
+        let root: Arc<PathBuf> = Arc::new(root.canonicalize().map_err(|e| format!("{}: {e}", root.display()))?);
+        let cache: Arc<FileCache> = Arc::new(FileCache::new(DEFAULT_CACHE_BYTES));
+        loop {
+            let (reader, writer): (Box<dyn Read + Send>, Box<dyn Write + Send>) = match self {
+                Listener::Tcp(listener) => {
+                    let (stream, _): (TcpStream, SocketAddr) = listener.accept()?;
+                    (Box::new(stream.try_clone()?), Box::new(stream))
+                }
+                #[cfg(unix)]
+                Listener::Unix(listener, _) => {
+                    let (stream, _) = listener.accept()?;
+                    (Box::new(stream.try_clone()?), Box::new(stream))
+                }
+            };
+            let (root, cache): (Arc<PathBuf>, Arc<FileCache>) = (Arc::clone(&root), Arc::clone(&cache));
+            thread::spawn(move || handle_connection(reader, writer, root, cache));
+        }
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        if let Some(path) = self.socket_path() {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::scratch_dir;
+
+    fn events(stream: &mut BufReader<TcpStream>) -> Vec<Value> {
+        let mut events: Vec<Value> = Vec::new();
+        loop {
+            let mut line: String = String::new();
+            stream.read_line(&mut line).unwrap();
+            let event: Value = serde_json::from_str(&line).unwrap();
+            let end: bool = is_last_event(&event);
+            events.push(event);
+            if end {
+                return events;
+            }
+        }
+    }
+
+    fn search(id: Value, params: Value) -> SearchRequest {
+        let line: String = json!({"jsonrpc": "2.0", "id": id, "method": "search", "params": params}).to_string();
+        return match parse_request(&line) {
+            Ok(Incoming::Search(request)) => request,
+            _ => unreachable!(),
+        };
+    }
+
+    fn run(request: &SearchRequest, root: &Path, cancel: bool) -> Vec<Value> {
+        let mut sent: Vec<Value> = Vec::new();
+        run_search(request, &root.canonicalize().unwrap(), &FileCache::new(1024), &AtomicBool::new(cancel), &mut |event| {
+            sent.push(event);
+            return true;
+        });
+        return sent;
+    }
+
+    #[test]
+    fn streams_matches_and_caches_files() {
+        let dir: PathBuf = scratch_dir("serve-tcp", &[("src/a.rs", "let c = Config::new(&args);\nother\n")]);
+        let listener: Listener = Listener::bind("127.0.0.1:0").unwrap();
+        let address: String = listener.address();
+        let root: PathBuf = dir.clone();
+        thread::spawn(move || {
+            let _ = listener.serve(&root);
+        });
+
+        let stream: TcpStream = TcpStream::connect(&address).unwrap();
+        let mut writer: TcpStream = stream.try_clone().unwrap();
+        let mut reader: BufReader<TcpStream> = BufReader::new(stream);
+        let params: Value = json!({"query": "config::new", "paths": ["src"], "options": {"ignore_case": true}});
+        let request: Value = json!({"jsonrpc": "2.0", "id": 7, "method": "search", "params": params});
+        writeln!(writer, "{request}").unwrap();
+        let first: Vec<Value> = events(&mut reader);
+        assert_eq!(("2.0", "match"), (first[0]["jsonrpc"].as_str().unwrap(), first[0]["method"].as_str().unwrap()));
+        assert_eq!(json!({"id": 7, "path": "src/a.rs", "line_number": 1, "line": "let c = Config::new(&args);", "submatches": [[8, 19]]}), first[0]["params"]);
+        assert_eq!((7, 0), (first[1]["id"].as_i64().unwrap(), first[1]["result"]["cache_hits"].as_i64().unwrap()));
+
+        writeln!(writer, "{request}").unwrap();
+        assert_eq!(1, events(&mut reader)[1]["result"]["cache_hits"]);
+        // An id can be reused as soon as its response has arrived.
+        for _ in 0..5 {
+            writeln!(writer, "{request}").unwrap();
+            assert!(events(&mut reader)[1]["result"].is_object());
+        }
+        writeln!(writer, "{}", json!({"jsonrpc": "2.0", "method": "search", "params": {"query": "x"}})).unwrap();
+        assert_eq!(json!({"code": INVALID_REQUEST, "message": "search needs an `id`"}), events(&mut reader)[0]["error"]);
+        writeln!(writer, "{}", json!({"jsonrpc": "2.0", "id": 8, "method": "replace"})).unwrap();
+        let unknown: Vec<Value> = events(&mut reader);
+        assert_eq!((8, METHOD_NOT_FOUND), (unknown[0]["id"].as_i64().unwrap(), unknown[0]["error"]["code"].as_i64().unwrap()));
+        writeln!(writer, "not json").unwrap();
+        let parse_error: Vec<Value> = events(&mut reader);
+        assert_eq!((Value::Null, PARSE_ERROR), (parse_error[0]["id"].clone(), parse_error[0]["error"]["code"].as_i64().unwrap()));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn cancelled_requests_stop_early() {
+        let dir: PathBuf = scratch_dir("serve-cancel", &[("a.txt", "x\nx\n")]);
+        let sent: Vec<Value> = run(&search(json!("a"), json!({"query": "x"})), &dir, true);
+        assert_eq!(1, sent.len());
+        let result: &Value = &sent[0]["result"];
+        assert_eq!((true, 0), (result["cancelled"].as_bool().unwrap(), result["stats"]["matched_lines"].as_u64().unwrap()));
+        let line: String = json!({"jsonrpc": "2.0", "method": "cancel", "params": {"id": "a"}}).to_string();
+        assert!(matches!(parse_request(&line), Ok(Incoming::Cancel(target, Value::Null)) if target == "a"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn only_searches_under_the_served_directory() {
+        let dir: PathBuf = scratch_dir("serve-root", &[("served/a.txt", "x\n"), ("private/b.txt", "x\n")]);
+        let root: PathBuf = dir.join("served");
+        assert_eq!(2, run(&search(json!(1), json!({"query": "x", "paths": ["."]})), &root, false).len());
+        for outside in [json!(["../private"]), json!([dir.join("private")]), json!(["/"])] {
+            let sent: Vec<Value> = run(&search(json!(1), json!({"query": "x", "paths": outside})), &root, false);
+            assert_eq!(1, sent.len());
+            assert_eq!(INVALID_PARAMS, sent[0]["error"]["code"], "{outside}");
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn only_listens_on_localhost() {
+        assert!(Listener::bind("0.0.0.0:0").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn removes_its_socket_when_done() {
+        let dir: PathBuf = scratch_dir("serve-unix", &[]);
+        let socket: PathBuf = dir.join("minigrep.sock");
+        let listener: Listener = Listener::bind(&format!("unix:{}", socket.display())).unwrap();
+        assert!(socket.exists());
+        drop(listener);
+        assert!(!socket.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
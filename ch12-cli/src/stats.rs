@@ -0,0 +1,46 @@
+// Counters describing how much work a search did. Searching one buffer or file produces a
+// Stats, and the Stats for several files add up with `+=`.
+
+use std::fmt;
+use std::ops::AddAssign;
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Stats {
+    pub files_searched: usize,
+    pub files_matched: usize,
+    pub bytes_read: u64,
+    pub lines_scanned: u64,
+    /// Individual matches, so a line with two matches counts twice.
+    pub matches: u64,
+    pub matched_lines: u64,
+    pub elapsed: Duration,
+    /// Files that couldn't be searched, with why (a --pre command that failed, say). The
+    /// search carries on past them, but they make it fail in the end.
+    pub errors: Vec<String>,
+}
+
+impl AddAssign for Stats {
+    fn add_assign(&mut self, other: Stats) {
+        self.files_searched += other.files_searched;
+        self.files_matched += other.files_matched;
+        self.bytes_read += other.bytes_read;
+        self.lines_scanned += other.lines_scanned;
+        self.matches += other.matches;
+        self.matched_lines += other.matched_lines;
+        self.elapsed += other.elapsed;
+        self.errors.extend(other.errors);
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} files searched", self.files_searched)?;
+        writeln!(f, "{} files contained matches", self.files_matched)?;
+        writeln!(f, "{} bytes read", self.bytes_read)?;
+        writeln!(f, "{} lines scanned", self.lines_scanned)?;
+        writeln!(f, "{} matched lines", self.matched_lines)?;
+        writeln!(f, "{} matches", self.matches)?;
+        return write!(f, "{:.6} seconds", self.elapsed.as_secs_f64());
+    }
+}
@@ -0,0 +1,143 @@
+// "Did you mean" suggestions for searches that found nothing. The words of the searched files
+// are counted, and each query term is compared with them by edit distance: insertions,
+// deletions, substitutions and swaps of two neighbouring letters all cost one, so `recieve` is
+// one edit away from `receive`. Words are compared ignoring case, which makes a term that only
+// failed because of its case a perfect suggestion. Closer words come first, then more frequent
+// ones.
+
+use std::collections::HashMap;
+
+/// How many suggestions to offer for one term.
+pub const MAX_SUGGESTIONS: usize = 3;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Suggestion {
+    pub word: String,
+    pub hits: u64,
+}
+
+/// Word counts, grouped by lowercase form. Each group is shown with its most common spelling.
+#[derive(Debug, Default)]
+pub struct Vocabulary {
+    words: HashMap<String, HashMap<String, u64>>,
+}
+
+fn words(text: &str) -> impl Iterator<Item = &str> {
+    return text.split(|c: char| !(c.is_alphanumeric() || c == '_')).filter(|word| !word.is_empty());
+}
+
+impl Vocabulary {
+    pub fn add_text(&mut self, text: &str) {
+        for word in words(text) {
+            *self.words.entry(word.to_lowercase()).or_default().entry(word.to_string()).or_default() += 1;
+        }
+    }
+
+    /// Whether `word` occurs in the text, spelled exactly like that or, with `ignore_case`, in
+    /// any case.
+    pub fn contains(&self, word: &str, ignore_case: bool) -> bool {
+        return match self.words.get(&word.to_lowercase()) {
+            Some(spellings) => ignore_case || spellings.contains_key(word),
+            None => false,
+        };
+    }
+
+    /// The words closest to `term`, best first. Short terms only allow one edit, since
+    /// everything is a few edits away from a three-letter word.
+    pub fn suggest(&self, term: &str) -> Vec<Suggestion> {
+        let lower: Vec<char> = term.to_lowercase().chars().collect();
+        let max_distance: usize = match lower.len() {
+            0..=2 => return Vec::new(),
+            3..=4 => 1,
+            5..=8 => 2,
+            _ => 3,
+        };
+        let mut found: Vec<(usize, Suggestion)> = Vec::new();
+        for (word, spellings) in &self.words {
+            let chars: Vec<char> = word.chars().collect();
+            if chars.len().abs_diff(lower.len()) > max_distance {
+                continue;
+            }
+            let distance: usize = edit_distance(&lower, &chars);
+            let (spelling, _) = spellings.iter().max_by(|a, b| a.1.cmp(b.1).then_with(|| b.0.cmp(a.0))).unwrap();
+            if distance > max_distance || spelling == term {
+                continue;
+            }
+            found.push((distance, Suggestion { word: spelling.clone(), hits: spellings.values().sum() }));
+        }
+        found.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.hits.cmp(&a.1.hits)).then_with(|| a.1.word.cmp(&b.1.word)));
+        return found.into_iter().take(MAX_SUGGESTIONS).map(|(_, suggestion)| suggestion).collect();
+    }
+}
+
+/// Optimal string alignment distance: Levenshtein distance where swapping two neighbouring
+/// characters is also a single edit.
+pub fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let mut rows: Vec<Vec<usize>> = vec![(0..=b.len()).collect()];
+    for i in 1..=a.len() {
+        let mut row: Vec<usize> = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let cost: usize = (a[i - 1] != b[j - 1]) as usize;
+            row[j] = (rows[i - 1][j] + 1).min(row[j - 1] + 1).min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                row[j] = row[j].min(rows[i - 2][j - 2] + 1);
+            }
+        }
+        rows.push(row);
+    }
+    return rows[a.len()][b.len()];
+}
+
+/// The message for a term with no matches, e.g.
+/// "no matches for `recieve`; did you mean `receive` (12 hits)?"
+pub fn message(term: &str, suggestions: &[Suggestion]) -> String {
+    if suggestions.is_empty() {
+        return format!("no matches for `{term}`");
+    }
+    let options: Vec<String> = suggestions
+        .iter()
+        .map(|s| format!("`{}` ({} {})", s.word, s.hits, if s.hits == 1 { "hit" } else { "hits" }))
+        .collect();
+    return format!("no matches for `{term}`; did you mean {}?", options.join(" or "));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn distance(a: &str, b: &str) -> usize {
+        let a: Vec<char> = a.chars().collect();
+        let b: Vec<char> = b.chars().collect();
+        return edit_distance(&a, &b);
+    }
+
+    #[test]
+    fn swaps_count_once() {
+        assert_eq!(1, distance("recieve", "receive"));
+        assert_eq!(3, distance("kitten", "sitting"));
+        assert_eq!(0, distance("", ""));
+    }
+
+    #[test]
+    fn closest_then_most_frequent() {
+        let mut vocabulary: Vocabulary = Vocabulary::default();
+        vocabulary.add_text("receive receive Receive; deceive. receiver");
+        let suggestions: Vec<Suggestion> = vocabulary.suggest("recieve");
+        let words: Vec<(&str, u64)> = suggestions.iter().map(|s| (s.word.as_str(), s.hits)).collect();
+        assert_eq!(vec![("receive", 3), ("deceive", 1), ("receiver", 1)], words);
+        assert_eq!(
+            "no matches for `recieve`; did you mean `receive` (3 hits) or `deceive` (1 hit) or `receiver` (1 hit)?",
+            message("recieve", &suggestions)
+        );
+        assert!(vocabulary.suggest("ab").is_empty());
+    }
+
+    #[test]
+    fn suggests_other_case() {
+        let mut vocabulary: Vocabulary = Vocabulary::default();
+        vocabulary.add_text("Rust is fun. Rust!");
+        assert_eq!(vec![Suggestion { word: String::from("Rust"), hits: 2 }], vocabulary.suggest("rust"));
+        assert!(!vocabulary.contains("rust", false));
+        assert!(vocabulary.contains("rust", true));
+    }
+}
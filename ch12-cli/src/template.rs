@@ -0,0 +1,200 @@
+// Output templates for --format, for turning matches into data:
+//
+//   --format '{path}\t{line_number}\t{user}\t{2}'
+//
+// `{path}`, `{line_number}`, `{line}` and `{match}` are filled in from the match, and a number
+// or any other name picks out a regex capture group. `\t`, `\n` and `\\` are escapes, and `{{`
+// and `}}` stand for literal braces. A template that mentions `{match}` or a capture group is
+// printed once per match; otherwise once per matching line. Each is followed by a newline.
+// Capture groups need a --regex pattern, and a --boolean query has none to offer. With --rank
+// the best line of each result stands in for the matching line.
+
+use regex::Regex;
+
+use crate::matcher::{Groups, Match};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Piece {
+    Text(String),
+    Path,
+    LineNumber,
+    Line,
+    Match,
+    Group(usize),
+    Named(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    pieces: Vec<Piece>,
+}
+
+/// What a template is filled in from. `matched` and the ranges in `groups` are byte offsets
+/// into `line`.
+pub struct Fields<'a> {
+    pub path: &'a str,
+    pub line_number: usize,
+    pub line: &'a str,
+    pub matched: Match,
+    pub groups: Option<&'a Groups>,
+}
+
+impl Template {
+    pub fn parse(text: &str) -> Result<Template, &'static str> {
+        let mut pieces: Vec<Piece> = Vec::new();
+        let mut literal: String = String::new();
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some('t') => literal.push('\t'),
+                    Some('n') => literal.push('\n'),
+                    Some('\\') => literal.push('\\'),
+                    Some(other) => {
+                        literal.push('\\');
+                        literal.push(other);
+                    }
+                    None => literal.push('\\'),
+                },
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '}' => return Err("--format has a `}` without a `{`; write `}}` for a brace!"),
+                '{' => {
+                    let mut name: String = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return Err("--format has a `{` without a `}`; write `{{` for a brace!"),
+                        }
+                    }
+                    if !literal.is_empty() {
+                        pieces.push(Piece::Text(std::mem::take(&mut literal)));
+                    }
+                    pieces.push(match name.as_str() {
+                        "" => return Err("--format has an empty `{}`!"),
+                        "path" => Piece::Path,
+                        "line_number" => Piece::LineNumber,
+                        "line" => Piece::Line,
+                        "match" => Piece::Match,
+                        _ => match name.parse() {
+                            Ok(index) => Piece::Group(index),
+                            Err(_) => Piece::Named(name),
+                        },
+                    });
+                }
+                _ => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            pieces.push(Piece::Text(literal));
+        }
+        return Ok(Template { pieces });
+    }
+
+    /// Whether the template is filled in once per match rather than once per line.
+    pub fn per_match(&self) -> bool {
+        return self.pieces.iter().any(|piece| matches!(piece, Piece::Match | Piece::Group(_) | Piece::Named(_)));
+    }
+
+    /// Whether the template mentions a capture group (other than `{0}`, the match itself).
+    pub fn uses_groups(&self) -> bool {
+        return self.pieces.iter().any(|piece| matches!(piece, Piece::Group(1..) | Piece::Named(_)));
+    }
+
+    /// Whether the template mentions `{line_number}`, so real line numbers have to be kept.
+    pub fn uses_line_number(&self) -> bool {
+        return self.pieces.contains(&Piece::LineNumber);
+    }
+
+    /// Checks that every capture group the template mentions is in `regex`.
+    pub fn check_groups(&self, regex: Option<&Regex>) -> Result<(), String> {
+        for piece in &self.pieces {
+            let (known, name): (bool, String) = match (piece, regex) {
+                (Piece::Group(0), _) => continue,
+                (Piece::Group(index), Some(regex)) => (*index < regex.captures_len(), index.to_string()),
+                (Piece::Named(name), Some(regex)) => (regex.capture_names().any(|n| n == Some(name)), name.clone()),
+                (Piece::Group(_) | Piece::Named(_), None) => {
+                    return Err(String::from("capture groups in --format need a --regex pattern"));
+                }
+                _ => continue,
+            };
+            if !known {
+                return Err(format!("--format uses `{{{name}}}`, which isn't a capture group in the pattern"));
+            }
+        }
+        return Ok(());
+    }
+
+    /// Fills in the template. Groups that didn't take part in the match are left empty.
+    pub fn render(&self, fields: &Fields) -> String {
+        let group = |index: Option<usize>| -> &str {
+            let found: Option<Match> = match (index, fields.groups) {
+                (Some(0), _) => Some(fields.matched),
+                (Some(index), Some(groups)) => groups.groups.get(index).copied().flatten(),
+                _ => None,
+            };
+            return found.map_or("", |m| &fields.line[m.start..m.end]);
+        };
+        let mut output: String = String::new();
+        for piece in &self.pieces {
+            match piece {
+                Piece::Text(text) => output.push_str(text),
+                Piece::Path => output.push_str(fields.path),
+                Piece::LineNumber => output.push_str(&fields.line_number.to_string()),
+                Piece::Line => output.push_str(fields.line),
+                Piece::Match => output.push_str(group(Some(0))),
+                Piece::Group(index) => output.push_str(group(Some(*index))),
+                Piece::Named(name) => {
+                    let index: Option<usize> = fields.groups.and_then(|groups| groups.names.iter().find(|(n, _)| n == name).map(|(_, i)| *i));
+                    output.push_str(group(index));
+                }
+            }
+        }
+        output.push('\n');
+        return output;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_placeholders_and_escapes() {
+        let template: Template = Template::parse("{path}:{line_number}\\t{{{match}}}\\\\{user}").unwrap();
+        assert!(template.per_match());
+        assert!(!Template::parse("{path}\\t{line}").unwrap().per_match());
+        assert!(template.uses_groups() && template.uses_line_number());
+        assert!(!Template::parse("{0}{match}").unwrap().uses_groups());
+        assert!(Template::parse("{path").is_err());
+        assert!(Template::parse("a}b").is_err());
+        let fields: Fields = Fields { path: "app.log", line_number: 7, line: "login bob", matched: Match::new(6, 9), groups: None };
+        assert_eq!("app.log:7\t{bob}\\\n", template.render(&fields));
+    }
+
+    #[test]
+    fn fills_in_capture_groups() {
+        let regex: Regex = Regex::new(r"user=(?P<user>\w+) took (\d+)(ms)?").unwrap();
+        let template: Template = Template::parse("{user}\\t{2}\\t{3}").unwrap();
+        assert_eq!(Ok(()), template.check_groups(Some(&regex)));
+        assert!(Template::parse("{4}").unwrap().check_groups(Some(&regex)).is_err());
+        assert!(Template::parse("{id}").unwrap().check_groups(Some(&regex)).is_err());
+        assert!(template.check_groups(None).is_err());
+
+        let line: &str = "GET user=ann took 31s";
+        let captures: regex::Captures = regex.captures(line).unwrap();
+        let groups: Groups = Groups {
+            groups: captures.iter().map(|m| m.map(|m| Match::new(m.start(), m.end()))).collect(),
+            names: vec![(String::from("user"), 1)],
+        };
+        let fields: Fields = Fields { path: "", line_number: 1, line, matched: Match::new(4, 19), groups: Some(&groups) };
+        assert_eq!("ann\t31\t\n", template.render(&fields));
+    }
+}
@@ -0,0 +1,21 @@
+// Scratch directories for tests. Each one is made fresh under the system temp directory, with
+// the test process's id in its name so parallel test runs don't trip over each other.
+
+use std::fs;
+use std::path::PathBuf;
+
+/// An empty directory for the test called `name`, holding `files` given as (relative path,
+/// contents) pairs. The test removes it when it's done.
+pub fn scratch_dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir: PathBuf = std::env::temp_dir().join(format!("minigrep-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    for (path, contents) in files {
+        let path: PathBuf = dir.join(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).unwrap();
+        }
+        fs::write(&path, contents).unwrap();
+    }
+    return dir;
+}
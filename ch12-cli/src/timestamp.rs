@@ -0,0 +1,459 @@
+// Restricting a search to the log entries inside a time window (--since/--until).
+//
+// Each line may start with a timestamp. By default RFC 3339 (`2024-05-01T12:00:00.5Z`, also with
+// a space instead of the T) and syslog (`May  1 12:00:00`) are recognised; `--time-format` takes a
+// strftime-like format instead. Lines without a timestamp belong to the entry above them, so a
+// stack trace stays with the log line that printed it. Syslog timestamps have no year, so they're
+// taken to be in the year the search started in. Times without an offset are treated as UTC, and
+// a bare date given to --until means the end of that day.
+//
+// On a log that's sorted by time the start of the window can be found by binary search over the
+// file, reading only a little around each probe instead of everything before the window.
+
+use std::error::Error;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Timestamp {
+    pub secs: i64,
+    pub nanos: u32,
+}
+
+const MONTHS: [&str; 12] = ["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+
+// Days since 1970-01-01 for a date in the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year: i64 = if month <= 2 { year - 1 } else { year };
+    let era: i64 = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era: i64 = year - era * 400;
+    let month: i64 = month as i64;
+    let day_of_year: i64 = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era: i64 = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    return era * 146097 + day_of_era - 719468;
+}
+
+/// The year it is now, for timestamps that don't say. A search works it out once, so every line
+/// is read the same way even if the search runs past New Year.
+pub fn current_year() -> i64 {
+    let secs: i64 = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0);
+    // Good enough for picking a year: count whole years forward from 1970.
+    let mut year: i64 = 1970;
+    while days_from_civil(year + 1, 1, 1) * 86400 <= secs {
+        year += 1;
+    }
+    return year;
+}
+
+#[derive(Debug, Default)]
+struct Fields {
+    year: Option<i64>,
+    month: u32,
+    day: u32,
+    hour: u32,
+    minute: u32,
+    second: u32,
+    nanos: u32,
+    offset_secs: i64,
+    epoch: Option<i64>,
+}
+
+impl Fields {
+    fn timestamp(&self, year: i64) -> Option<Timestamp> {
+        if let Some(epoch) = self.epoch {
+            return Some(Timestamp { secs: epoch, nanos: self.nanos });
+        }
+        if !(1..=12).contains(&self.month) || !(1..=31).contains(&self.day) || self.hour > 23 || self.minute > 59 || self.second > 60 {
+            return None;
+        }
+        let year: i64 = self.year.unwrap_or(year);
+        let days: i64 = days_from_civil(year, self.month, self.day);
+        let secs: i64 = days * 86400 + (self.hour * 3600 + self.minute * 60 + self.second) as i64 - self.offset_secs;
+        return Some(Timestamp { secs, nanos: self.nanos });
+    }
+}
+
+// A little cursor over the start of a line.
+struct Scanner<'a> {
+    text: &'a [u8],
+    pos: usize,
+}
+
+impl Scanner<'_> {
+    fn digits(&mut self, min: usize, max: usize) -> Option<i64> {
+        let start: usize = self.pos;
+        while self.pos < self.text.len() && self.pos - start < max && self.text[self.pos].is_ascii_digit() {
+            self.pos += 1;
+        }
+        if self.pos - start < min {
+            return None;
+        }
+        return std::str::from_utf8(&self.text[start..self.pos]).ok()?.parse().ok();
+    }
+
+    fn eat(&mut self, c: u8) -> bool {
+        if self.text.get(self.pos) == Some(&c) {
+            self.pos += 1;
+            return true;
+        }
+        return false;
+    }
+
+    fn fraction(&mut self) -> u32 {
+        let start: usize = self.pos;
+        let mut nanos: u32 = 0;
+        while self.pos < self.text.len() && self.text[self.pos].is_ascii_digit() {
+            if self.pos - start < 9 {
+                nanos = nanos * 10 + (self.text[self.pos] - b'0') as u32;
+            }
+            self.pos += 1;
+        }
+        for _ in (self.pos - start)..9 {
+            nanos *= 10;
+        }
+        return nanos;
+    }
+
+    fn month_name(&mut self) -> Option<u32> {
+        let word: &[u8] = self.text.get(self.pos..self.pos + 3)?;
+        let word: String = String::from_utf8_lossy(word).to_lowercase();
+        let month: usize = MONTHS.iter().position(|m| *m == word)?;
+        self.pos += 3;
+        return Some(month as u32 + 1);
+    }
+
+    // Z, +hh:mm, +hhmm or +hh.
+    fn offset(&mut self) -> Option<i64> {
+        if self.eat(b'Z') || self.eat(b'z') {
+            return Some(0);
+        }
+        let sign: i64 = if self.eat(b'+') {
+            1
+        } else if self.eat(b'-') {
+            -1
+        } else {
+            return None;
+        };
+        let hours: i64 = self.digits(2, 2)?;
+        self.eat(b':');
+        let minutes: i64 = self.digits(2, 2).unwrap_or(0);
+        return Some(sign * (hours * 3600 + minutes * 60));
+    }
+}
+
+// The timestamp, and whether it had a time of day or was only a date.
+fn parse_rfc3339(line: &str) -> Option<(Timestamp, bool)> {
+    let mut s: Scanner = Scanner { text: line.as_bytes(), pos: 0 };
+    let mut fields: Fields = Fields { year: Some(s.digits(4, 4)?), ..Fields::default() };
+    if !s.eat(b'-') {
+        return None;
+    }
+    fields.month = s.digits(2, 2)? as u32;
+    if !s.eat(b'-') {
+        return None;
+    }
+    fields.day = s.digits(2, 2)? as u32;
+    // A bare date means midnight. The separator only starts a time when a digit follows it, so
+    // `2024-05-01 server started` is still dated.
+    let has_time: bool = matches!(s.text.get(s.pos), Some(b'T' | b't' | b' ')) && s.text.get(s.pos + 1).is_some_and(u8::is_ascii_digit);
+    if has_time {
+        s.pos += 1;
+        fields.hour = s.digits(2, 2)? as u32;
+        if !s.eat(b':') {
+            return None;
+        }
+        fields.minute = s.digits(2, 2)? as u32;
+        if s.eat(b':') {
+            fields.second = s.digits(2, 2)? as u32;
+            if s.eat(b'.') || s.eat(b',') {
+                fields.nanos = s.fraction();
+            }
+        }
+        s.eat(b' ');
+        fields.offset_secs = s.offset().unwrap_or(0);
+    }
+    // RFC 3339 always has a year, so the fallback is never used.
+    return Some((fields.timestamp(0)?, has_time));
+}
+
+fn parse_syslog(line: &str, year: i64) -> Option<Timestamp> {
+    let mut s: Scanner = Scanner { text: line.as_bytes(), pos: 0 };
+    let mut fields: Fields = Fields { month: s.month_name()?, ..Fields::default() };
+    if !s.eat(b' ') {
+        return None;
+    }
+    s.eat(b' ');
+    fields.day = s.digits(1, 2)? as u32;
+    if !s.eat(b' ') {
+        return None;
+    }
+    fields.hour = s.digits(2, 2)? as u32;
+    if !s.eat(b':') {
+        return None;
+    }
+    fields.minute = s.digits(2, 2)? as u32;
+    if !s.eat(b':') {
+        return None;
+    }
+    fields.second = s.digits(2, 2)? as u32;
+    return fields.timestamp(year);
+}
+
+// Supports %Y %y %m %d %e %b %H %M %S %f %z %s and %%; anything else must match literally.
+fn parse_with_format(line: &str, format: &str, year: i64) -> Option<Timestamp> {
+    let mut s: Scanner = Scanner { text: line.as_bytes(), pos: 0 };
+    let mut fields: Fields = Fields::default();
+    let mut spec = format.bytes();
+    while let Some(c) = spec.next() {
+        if c != b'%' {
+            if !s.eat(c) {
+                return None;
+            }
+            continue;
+        }
+        match spec.next()? {
+            b'Y' => fields.year = Some(s.digits(4, 4)?),
+            b'y' => fields.year = Some(2000 + s.digits(2, 2)?),
+            b'm' => fields.month = s.digits(1, 2)? as u32,
+            b'd' => fields.day = s.digits(1, 2)? as u32,
+            b'e' => {
+                s.eat(b' ');
+                fields.day = s.digits(1, 2)? as u32;
+            }
+            b'b' => fields.month = s.month_name()?,
+            b'H' => fields.hour = s.digits(1, 2)? as u32,
+            b'M' => fields.minute = s.digits(1, 2)? as u32,
+            b'S' => fields.second = s.digits(1, 2)? as u32,
+            b'f' => fields.nanos = s.fraction(),
+            b'z' => fields.offset_secs = s.offset()?,
+            b's' => fields.epoch = Some(s.digits(1, 19)?),
+            b'%' => {
+                if !s.eat(b'%') {
+                    return None;
+                }
+            }
+            _ => return None,
+        }
+    }
+    return fields.timestamp(year);
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum TimeFormat {
+    /// RFC 3339 or syslog, whichever the line starts with.
+    #[default]
+    Auto,
+    /// A strftime-like format such as `%d/%b/%Y:%H:%M:%S %z`.
+    Custom(String),
+}
+
+impl TimeFormat {
+    /// The timestamp at the start of `line`, if it has one. Timestamps without a year, like
+    /// syslog's, are taken to be in `year`.
+    pub fn parse_prefix(&self, line: &str, year: i64) -> Option<Timestamp> {
+        return match self {
+            TimeFormat::Auto => parse_rfc3339(line).map(|(time, _)| time).or_else(|| parse_syslog(line, year)),
+            TimeFormat::Custom(format) => parse_with_format(line, format, year),
+        };
+    }
+
+    /// Parses a --since/--until value. RFC 3339 and syslog times are always accepted, as is
+    /// anything in the custom format.
+    pub fn parse_bound(&self, text: &str, year: i64) -> Result<Timestamp, Box<dyn Error>> {
+        let parsed: Option<Timestamp> = match self {
+            TimeFormat::Custom(format) => parse_with_format(text, format, year),
+            TimeFormat::Auto => None,
+        };
+        return parsed
+            .or_else(|| TimeFormat::Auto.parse_prefix(text, year))
+            .ok_or_else(|| format!("can't read `{text}` as a time").into());
+    }
+
+    /// Parses an --until value like `parse_bound`, except that a bare RFC 3339 date means the
+    /// end of that day, so `--until 2024-05-01` keeps everything logged on May 1st.
+    pub fn parse_until(&self, text: &str, year: i64) -> Result<Timestamp, Box<dyn Error>> {
+        if let TimeFormat::Custom(format) = self
+            && let Some(time) = parse_with_format(text, format, year)
+        {
+            return Ok(time);
+        }
+        if let Some((time, false)) = parse_rfc3339(text) {
+            return Ok(Timestamp { secs: time.secs + 86399, nanos: 999_999_999 });
+        }
+        return self.parse_bound(text, year);
+    }
+}
+
+/// A run of consecutive lines inside the window, starting at line `first_line`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment<'a> {
+    pub first_line: usize,
+    pub text: &'a str,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct TimeWindow {
+    pub since: Option<Timestamp>,
+    pub until: Option<Timestamp>,
+    pub format: TimeFormat,
+    /// The year for timestamps that don't have one.
+    pub year: i64,
+}
+
+impl TimeWindow {
+    pub fn contains(&self, time: Timestamp) -> bool {
+        return self.since.is_none_or(|since| time >= since) && self.until.is_none_or(|until| time <= until);
+    }
+
+    /// Splits `text` into the runs of lines that are inside the window. Lines before the first
+    /// timestamp don't belong to any entry and are left out. With `sorted`, the scan stops at
+    /// the first entry after the window.
+    pub fn segments<'a>(&self, text: &'a str, sorted: bool) -> Vec<Segment<'a>> {
+        let mut segments: Vec<Segment> = Vec::new();
+        let mut current: Option<(usize, usize)> = None;
+        let mut inside: bool = false;
+        let mut offset: usize = 0;
+        for (index, line) in text.split_inclusive('\n').enumerate() {
+            if let Some(time) = self.format.parse_prefix(line, self.year) {
+                inside = self.contains(time);
+                if sorted && self.until.is_some_and(|until| time > until) {
+                    break;
+                }
+            }
+            if inside {
+                if current.is_none() {
+                    current = Some((index + 1, offset));
+                }
+            } else if let Some((first_line, start)) = current.take() {
+                segments.push(Segment { first_line, text: &text[start..offset] });
+            }
+            offset += line.len();
+        }
+        if let Some((first_line, start)) = current {
+            segments.push(Segment { first_line, text: &text[start..offset] });
+        }
+        return segments;
+    }
+
+    /// For a file sorted by time, the offset of the first line whose entry could be inside the
+    /// window. Binary search over the file: each probe reads from the probed offset to the next
+    /// timestamped line.
+    pub fn seek_start<R: Read + Seek>(&self, file: &mut R, len: u64) -> io::Result<u64> {
+        let since: Timestamp = match self.since {
+            Some(since) => since,
+            None => return Ok(0),
+        };
+        let (mut lo, mut hi): (u64, u64) = (0, len);
+        let mut answer: u64 = len;
+        while lo < hi {
+            let mid: u64 = lo + (hi - lo) / 2;
+            match self.first_entry_at(file, mid, len)? {
+                Some((line_start, time)) if time >= since => {
+                    answer = answer.min(line_start);
+                    hi = mid;
+                }
+                Some((line_start, _)) => lo = line_start.max(mid) + 1,
+                None => hi = mid,
+            }
+        }
+        return Ok(answer);
+    }
+
+    // The first timestamped line starting at or after `from`, and its time.
+    fn first_entry_at<R: Read + Seek>(&self, file: &mut R, from: u64, len: u64) -> io::Result<Option<(u64, Timestamp)>> {
+        // Back up one byte so a probe that lands exactly on a line start keeps that line.
+        let read_from: u64 = from.saturating_sub(1);
+        file.seek(SeekFrom::Start(read_from))?;
+        let mut buffer: Vec<u8> = Vec::new();
+        let mut chunk: Vec<u8> = vec![0; 8192];
+        let mut pos: u64 = read_from;
+        let mut skip_partial: bool = from > 0;
+        loop {
+            let n: usize = file.read(&mut chunk)?;
+            buffer.extend_from_slice(&chunk[..n]);
+            let mut start: usize = 0;
+            while let Some(newline) = buffer[start..].iter().position(|&b| b == b'\n') {
+                let line: &[u8] = &buffer[start..start + newline];
+                if !skip_partial
+                    && let Some(time) = self.format.parse_prefix(&String::from_utf8_lossy(line), self.year)
+                {
+                    return Ok(Some((pos, time)));
+                }
+                skip_partial = false;
+                start += newline + 1;
+                pos += newline as u64 + 1;
+            }
+            buffer.drain(..start);
+            if n == 0 || pos >= len {
+                if !skip_partial
+                    && !buffer.is_empty()
+                    && let Some(time) = self.format.parse_prefix(&String::from_utf8_lossy(&buffer), self.year)
+                {
+                    return Ok(Some((pos, time)));
+                }
+                return Ok(None);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn at(text: &str) -> Timestamp {
+        return TimeFormat::Auto.parse_bound(text, 2024).unwrap();
+    }
+
+    #[test]
+    fn formats() {
+        assert_eq!(Timestamp { secs: 1714564800, nanos: 500_000_000 }, at("2024-05-01T12:00:00.5Z"));
+        assert_eq!(at("2024-05-01T12:00:00Z"), at("2024-05-01 14:00:00+02:00"));
+        let custom: TimeFormat = TimeFormat::Custom(String::from("[%d/%b/%Y:%H:%M:%S %z]"));
+        assert_eq!(Some(at("2024-05-01T12:00:00Z")), custom.parse_prefix("[01/May/2024:14:00:00 +0200] GET /", 2024));
+        assert_eq!(at("2024-10-03T04:05:06Z"), at("Oct  3 04:05:06 host sshd[1]: hi"));
+        assert_eq!(Some(at("1999-10-03T04:05:06Z")), TimeFormat::Auto.parse_prefix("Oct  3 04:05:06 host", 1999));
+        assert!(TimeFormat::Auto.parse_prefix("no time here", 2024).is_none());
+        assert_eq!(Some(at("2024-05-01T00:00:00Z")), TimeFormat::Auto.parse_prefix("2024-05-01 server started", 2024));
+        assert_eq!(Some(at("2024-05-01T00:00:00Z")), TimeFormat::Auto.parse_prefix("2024-05-01T", 2024));
+        assert!(current_year() >= 2024);
+    }
+
+    const LOG: &str = "\
+2024-05-01T10:00:00Z start
+2024-05-01T11:00:00Z error one
+  at frame 1
+2024-05-01T12:00:00Z error two
+2024-05-01T13:00:00Z done
+";
+
+    #[test]
+    fn continuation_lines_follow_their_entry() {
+        let window: TimeWindow = TimeWindow { since: Some(at("2024-05-01T11:00:00Z")), until: Some(at("2024-05-01T12:00:00Z")), ..TimeWindow::default() };
+        let segments: Vec<Segment> = window.segments(LOG, false);
+        assert_eq!(1, segments.len());
+        assert_eq!(2, segments[0].first_line);
+        assert_eq!("2024-05-01T11:00:00Z error one\n  at frame 1\n2024-05-01T12:00:00Z error two\n", segments[0].text);
+    }
+
+    #[test]
+    fn binary_search_finds_window_start() {
+        let window: TimeWindow = TimeWindow { since: Some(at("2024-05-01T10:30:00Z")), ..TimeWindow::default() };
+        let start: u64 = window.seek_start(&mut Cursor::new(LOG), LOG.len() as u64).unwrap();
+        assert!(LOG[start as usize..].starts_with("2024-05-01T11:00:00Z error one"));
+        let late: TimeWindow = TimeWindow { since: Some(at("2025-01-01")), ..TimeWindow::default() };
+        assert_eq!(LOG.len() as u64, late.seek_start(&mut Cursor::new(LOG), LOG.len() as u64).unwrap());
+        let early: TimeWindow = TimeWindow { since: Some(at("2020-01-01")), ..TimeWindow::default() };
+        assert_eq!(0, early.seek_start(&mut Cursor::new(LOG), LOG.len() as u64).unwrap());
+    }
+
+    #[test]
+    fn bare_date_until_covers_the_whole_day() {
+        let until: Timestamp = TimeFormat::Auto.parse_until("2024-05-01", 2024).unwrap();
+        assert_eq!(Timestamp { secs: at("2024-05-02T00:00:00Z").secs - 1, nanos: 999_999_999 }, until);
+        assert_eq!(at("2024-05-01T12:00:00Z"), TimeFormat::Auto.parse_until("2024-05-01T12:00:00Z", 2024).unwrap());
+        let window: TimeWindow = TimeWindow { until: Some(until), ..TimeWindow::default() };
+        assert_eq!(LOG, window.segments(LOG, true)[0].text);
+    }
+}
@@ -0,0 +1,80 @@
+// Collects the files a directory search looks at. Hidden files and directories (names starting
+// with a dot) are skipped, as is anything the FileFilter rejects, and the result is sorted so
+// output order doesn't depend on the filesystem. Filtering only looks at names, so rejected
+// files are never opened.
+//
+// Files that turn out to be binary are skipped when they're read. Like grep and ripgrep, a file
+// is binary if it has a NUL byte in its first block; text that isn't UTF-8 can't be searched
+// either, so that counts too.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::glob::FileFilter;
+
+pub fn walk(root: &Path) -> io::Result<Vec<PathBuf>> {
+    return walk_filtered(root, &FileFilter::default());
+}
+
+/// Like `walk`, but only keeps files `filter` allows. A file named directly is always kept.
+pub fn walk_filtered(root: &Path, filter: &FileFilter) -> io::Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = Vec::new();
+    if root.is_dir() {
+        walk_dir(root, root, filter, &mut files)?;
+    } else {
+        files.push(root.to_path_buf());
+    }
+    files.sort();
+    return Ok(files);
+}
+
+fn walk_dir(root: &Path, dir: &Path, filter: &FileFilter, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry: fs::DirEntry = entry?;
+        let path: PathBuf = entry.path();
+        if is_hidden(&path) {
+            continue;
+        }
+        let relative: &Path = path.strip_prefix(root).unwrap_or(&path);
+        let file_type: fs::FileType = entry.file_type()?;
+        if file_type.is_dir() {
+            if filter.allows_dir(relative) {
+                walk_dir(root, &path, filter, files)?;
+            }
+        } else if file_type.is_file() && filter.allows_file(relative) {
+            files.push(path);
+        }
+    }
+    return Ok(());
+}
+
+/// How much of the start of a file is checked for NUL bytes.
+pub const BINARY_CHECK_SIZE: usize = 8 * 1024;
+
+/// Whether text that starts with `bytes` looks binary.
+pub fn looks_binary(bytes: &[u8]) -> bool {
+    return bytes[..bytes.len().min(BINARY_CHECK_SIZE)].contains(&0);
+}
+
+/// The error reading a binary file fails with, the same one a file that isn't UTF-8 gives.
+pub fn binary_error() -> io::Error {
+    return io::Error::new(io::ErrorKind::InvalidData, "binary file");
+}
+
+/// Reads the file at `path` as text. Binary files fail with InvalidData, except that NULs are
+/// allowed with `allow_nul`, for searching NUL-separated records.
+pub fn read_text(path: &Path, allow_nul: bool) -> io::Result<String> {
+    let contents: String = fs::read_to_string(path)?;
+    if !allow_nul && looks_binary(contents.as_bytes()) {
+        return Err(binary_error());
+    }
+    return Ok(contents);
+}
+
+fn is_hidden(path: &Path) -> bool {
+    return match path.file_name() {
+        Some(name) => name.to_string_lossy().starts_with('.'),
+        None => false,
+    };
+}
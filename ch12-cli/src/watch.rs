@@ -0,0 +1,124 @@
+// Watch mode: re-run a search whenever one of the searched files changes, for a live view of
+// something like "every remaining use of Config::new" during a refactor. Like follow mode it
+// polls instead of using a platform notify API. A change is any searched file whose mtime or
+// size differs, or a file appearing or disappearing from the set that would be searched.
+//
+// Results are compared as whole formatted matches, so a line that moved (and so got a new line
+// number) shows up as one removal and one addition.
+
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::time::{Duration, SystemTime};
+
+use crate::follow;
+
+/// The modification time and size of each searched file at one moment.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Snapshot {
+    files: BTreeMap<PathBuf, Option<(SystemTime, u64)>>,
+}
+
+impl Snapshot {
+    /// Files that can't be read are recorded as missing, so it counts as a change when they
+    /// come back.
+    pub fn take(files: &[PathBuf]) -> Snapshot {
+        let mut snapshot: Snapshot = Snapshot::default();
+        for path in files {
+            let state: Option<(SystemTime, u64)> = fs::metadata(path)
+                .ok()
+                .map(|metadata| (metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH), metadata.len()));
+            snapshot.files.insert(path.clone(), state);
+        }
+        return snapshot;
+    }
+}
+
+/// The results in `new` but not in `old`, and those in `old` but not in `new`. Duplicates are
+/// counted, so one of two identical lines going away is still reported.
+pub fn diff<'a>(old: &'a [String], new: &'a [String]) -> (Vec<&'a str>, Vec<&'a str>) {
+    let mut counts: HashMap<&str, isize> = HashMap::new();
+    for result in old {
+        *counts.entry(result).or_default() -= 1;
+    }
+    for result in new {
+        *counts.entry(result).or_default() += 1;
+    }
+    let mut added: Vec<&str> = Vec::new();
+    for result in new {
+        let count: &mut isize = counts.get_mut(result.as_str()).unwrap();
+        if *count > 0 {
+            added.push(result);
+            *count -= 1;
+        }
+    }
+    let mut removed: Vec<&str> = Vec::new();
+    for result in old {
+        let count: &mut isize = counts.get_mut(result.as_str()).unwrap();
+        if *count < 0 {
+            removed.push(result);
+            *count += 1;
+        }
+    }
+    return (added, removed);
+}
+
+/// Takes a new snapshot every `interval` until it differs from `previous`. Returns false if
+/// `stop` was set first.
+pub fn wait_for_change<F>(previous: &Snapshot, interval: Duration, stop: &AtomicBool, mut take: F) -> Result<bool, Box<dyn Error>>
+where
+    F: FnMut() -> Result<Snapshot, Box<dyn Error>>,
+{
+    loop {
+        if !follow::sleep_unless_stopped(interval, stop) {
+            return Ok(false);
+        }
+        if take()? != *previous {
+            return Ok(true);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::scratch_dir;
+
+    fn strings(lines: &[&str]) -> Vec<String> {
+        return lines.iter().map(|line| line.to_string()).collect();
+    }
+
+    #[test]
+    fn diff_counts_duplicates() {
+        let old: Vec<String> = strings(&["a:1:x\n", "a:2:y\n", "a:2:y\n"]);
+        let new: Vec<String> = strings(&["a:2:y\n", "a:3:z\n"]);
+        let (added, removed): (Vec<&str>, Vec<&str>) = diff(&old, &new);
+        assert_eq!(vec!["a:3:z\n"], added);
+        assert_eq!(vec!["a:1:x\n", "a:2:y\n"], removed);
+    }
+
+    #[test]
+    fn snapshot_notices_size_changes_and_new_files() {
+        let dir: PathBuf = scratch_dir("watch", &[("lib.rs", "Config::new\n")]);
+        let path: PathBuf = dir.join("lib.rs");
+        let before: Snapshot = Snapshot::take(std::slice::from_ref(&path));
+        assert_eq!(before, Snapshot::take(std::slice::from_ref(&path)));
+        fs::write(&path, "Config::build\n").unwrap();
+        assert_ne!(before, Snapshot::take(std::slice::from_ref(&path)));
+        let other: PathBuf = dir.join("main.rs");
+        assert_ne!(before, Snapshot::take(&[path.clone(), other]));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn stops_waiting_when_asked() {
+        let stop: AtomicBool = AtomicBool::new(true);
+        let changed: bool = wait_for_change(&Snapshot::default(), Duration::from_millis(10), &stop, || {
+            return Ok(Snapshot::default());
+        })
+        .unwrap();
+        assert!(!changed);
+    }
+}